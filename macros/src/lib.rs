@@ -0,0 +1,555 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{
+    parse_macro_input, Attribute, Error, Expr, FnArg, GenericArgument, ImplItem, ImplItemFn,
+    ItemImpl, ItemTrait, Lit, Meta, Pat, PathArguments, ReturnType, TraitItem, Type,
+};
+
+/// Implements SharedObject for the type of an impl block. Every method of the block that
+/// takes `&self` can be called remotely by its name, except the ones marked `#[ipc(skip)]`.
+///
+/// The arguments of a method are read from the fields of the parameter of the call with
+/// the same name, and deserialized with serde. An argument of type Option may be left out.
+/// The value returned by a method is serialized with serde. If it returns a Result, its
+/// error is serialized into the error of the call.
+///
+/// The doc comments of the methods and the JSON Schema of their arguments and of their
+/// result are sent along with the registration of the object.
+///
+/// ```ignore
+/// use ipc_client::ipc_object;
+///
+/// struct Basket;
+///
+/// #[ipc_object]
+/// impl Basket {
+///     /// Puts fruits in the basket.
+///     async fn add(&self, fruit: String, count: Option<u32>) -> Result<u32, String> {
+///         Ok(count.unwrap_or(1))
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ipc_object(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(
+            proc_macro2::Span::call_site(),
+            "ipc_object takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let mut item = parse_macro_input!(item as ItemImpl);
+    match expand(&mut item) {
+        Ok(shared_object) => quote!(#item #shared_object).into(),
+        Err(e) => {
+            let e = e.to_compile_error();
+            quote!(#item #e).into()
+        }
+    }
+}
+
+/// Generates a typed proxy of the objects that implement the trait, named after the trait
+/// with a `Proxy` suffix. The proxy wraps a Connector and has an async method per method of
+/// the trait, which serializes its arguments into the fields of the parameter of the call
+/// and deserializes the result, the way `#[ipc_object]` reads and writes them.
+///
+/// Each method of the proxy returns a Result whose error is the Error of the call, and
+/// whose value is the value of the method of the trait, or of its Result.
+///
+/// ```ignore
+/// use ipc_client::ipc_proxy;
+///
+/// #[ipc_proxy]
+/// trait Basket {
+///     async fn add(&self, fruit: String, count: Option<u32>) -> Result<u32, String>;
+/// }
+///
+/// let basket = BasketProxy::new(connector, "basket");
+/// let count = basket.add("mango".into(), None).await?;
+/// ```
+#[proc_macro_attribute]
+pub fn ipc_proxy(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(
+            proc_macro2::Span::call_site(),
+            "ipc_proxy takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let item = parse_macro_input!(item as ItemTrait);
+    match expand_proxy(&item) {
+        Ok(proxy) => quote!(#item #proxy).into(),
+        Err(e) => {
+            let e = e.to_compile_error();
+            quote!(#item #e).into()
+        }
+    }
+}
+
+fn expand_proxy(item: &ItemTrait) -> syn::Result<TokenStream2> {
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &item.generics,
+            "ipc_proxy does not support generic traits",
+        ));
+    }
+
+    // Hygienic, so they cannot clash with the arguments.
+    let args = syn::Ident::new("args", proc_macro2::Span::mixed_site());
+    let response = syn::Ident::new("response", proc_macro2::Span::mixed_site());
+    let mut methods = Vec::new();
+    for item in &item.items {
+        let TraitItem::Fn(function) = item else {
+            continue;
+        };
+        let sig = &function.sig;
+        match sig.inputs.first() {
+            Some(FnArg::Receiver(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+            _ => {
+                return Err(Error::new_spanned(
+                    sig,
+                    "the methods of a proxied trait take &self",
+                ))
+            }
+        }
+
+        let mut inputs = Vec::new();
+        let mut pushes = Vec::new();
+        for input in sig.inputs.iter().skip(1) {
+            let FnArg::Typed(input) = input else {
+                continue;
+            };
+            let Pat::Ident(pat) = &*input.pat else {
+                return Err(Error::new_spanned(
+                    &input.pat,
+                    "the arguments of a proxied method must be plain names",
+                ));
+            };
+            let (arg, ty) = (&pat.ident, &input.ty);
+            let field = arg.unraw().to_string();
+            inputs.push(quote!(#arg: #ty));
+            pushes.push(quote!(#args.push(#field, &#arg)?;));
+        }
+
+        let value = match &sig.output {
+            ReturnType::Default => None,
+            ReturnType::Type(_, ty) => Some(result_ok(ty).unwrap_or(ty)),
+        };
+        // "OK" is read as a value only by the methods that have none.
+        let (value, read) = match value {
+            Some(value) if !is_unit(value) => (quote!(#value), quote!(from_response)),
+            _ => (quote!(()), quote!(from_unit_response)),
+        };
+        let ident = &sig.ident;
+        let name = ident.unraw().to_string();
+        let docs = function
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"));
+        methods.push(quote! {
+            #(#docs)*
+            pub async fn #ident(&self, #(#inputs),*) -> ::std::result::Result<#value, ::ipc_client::client::error::Error> {
+                use ::ipc_client::client::typed;
+                let mut #args = typed::Args::new();
+                #(#pushes)*
+                let #response = self.connector.remote_call_value(&self.object, #name, #args.into_param()).await?;
+                typed::#read(#response)
+            }
+        });
+    }
+
+    let vis = &item.vis;
+    let trait_name = &item.ident;
+    let proxy = quote::format_ident!("{}Proxy", trait_name);
+    let doc = format!(
+        "A typed proxy of the shared objects that implement {}.",
+        trait_name
+    );
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Clone, Debug)]
+        #vis struct #proxy {
+            connector: ::ipc_client::client::connector::Connector,
+            object: ::std::string::String,
+        }
+
+        impl #proxy {
+            /// Creates a proxy of the object, whose calls go through the connector.
+            pub fn new(connector: ::ipc_client::client::connector::Connector, object: &str) -> Self {
+                Self {
+                    connector,
+                    object: object.to_string(),
+                }
+            }
+
+            /// Returns the connector the calls go through.
+            pub fn connector(&self) -> &::ipc_client::client::connector::Connector {
+                &self.connector
+            }
+
+            #(#methods)*
+        }
+    })
+}
+
+/// A method of the impl block that can be called remotely.
+struct Method {
+    name: String,
+    description: Option<String>,
+    /// The name and the type of each argument.
+    args: Vec<(syn::Ident, Type)>,
+    output: Option<Type>,
+    call: TokenStream2,
+    /// True if the method returns a Result.
+    fallible: bool,
+    /// True if the method has no value, which is answered with "OK".
+    unit: bool,
+}
+
+fn expand(item: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "ipc_object goes on an impl block without a trait",
+        ));
+    }
+
+    let mut methods = Vec::new();
+    for item in &mut item.items {
+        if let ImplItem::Fn(function) = item {
+            if take_skip(&mut function.attrs)? {
+                continue;
+            }
+            if let Some(method) = method(function)? {
+                methods.push(method);
+            }
+        }
+    }
+
+    // Hygienic, so it cannot clash with the arguments.
+    let params = syn::Ident::new("params", proc_macro2::Span::mixed_site());
+    let arms = methods.iter().map(|method| {
+        let name = &method.name;
+        let call = &method.call;
+        let args = method.args.iter().map(|(arg, ty)| {
+            let field = arg.unraw().to_string();
+            quote!(let #arg: #ty = #params.take(#field)?;)
+        });
+        let params = match method.args.is_empty() {
+            true => quote!(typed::Params::new(param)?;),
+            false => quote!(let mut #params = typed::Params::new(param)?;),
+        };
+        let response = match (method.fallible, method.unit) {
+            (true, true) => quote! {
+                match #call {
+                    Ok(_) => Ok(typed::unit_response()),
+                    Err(error) => Err(typed::to_error(error)),
+                }
+            },
+            (true, false) => quote! {
+                match #call {
+                    Ok(value) => typed::to_elem(&value),
+                    Err(error) => Err(typed::to_error(error)),
+                }
+            },
+            (false, true) => quote! {
+                {
+                    #call;
+                    Ok(typed::unit_response())
+                }
+            },
+            (false, false) => quote!(typed::to_elem(&#call)),
+        };
+        quote! {
+            #name => {
+                #params
+                #(#args)*
+                #response
+            }
+        }
+    });
+
+    let descriptions = methods.iter().map(|method| {
+        let name = &method.name;
+        let description = method
+            .description
+            .as_ref()
+            .map(|description| quote!(.with_description(#description)));
+        let params = (!method.args.is_empty()).then(|| {
+            let schema = params_schema(&method.args);
+            quote!(.with_params(typed::schema(#schema)))
+        });
+        let result = method.output.as_ref().and_then(schema).map(|schema| {
+            let schema = schema.to_string();
+            quote!(.with_result(typed::schema(#schema)))
+        });
+        quote! {
+            ::ipc_client::client::message::MethodDescription::new(#name)
+                #description
+                #params
+                #result
+        }
+    });
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #[::ipc_client::__private::async_trait]
+        impl #impl_generics ::ipc_client::client::shared_object::SharedObject for #self_ty #where_clause {
+            async fn remote_call(
+                &self,
+                method: &str,
+                param: ::std::option::Option<::ipc_client::__private::json_elem::jsonelem::JsonElem>,
+            ) -> ::std::result::Result<
+                ::ipc_client::__private::json_elem::jsonelem::JsonElem,
+                ::ipc_client::client::error::Error,
+            > {
+                self.remote_call_value(method, param)
+                    .await
+                    .map(::ipc_client::client::typed::null_as_ok)
+            }
+
+            async fn remote_call_value(
+                &self,
+                method: &str,
+                param: ::std::option::Option<::ipc_client::__private::json_elem::jsonelem::JsonElem>,
+            ) -> ::std::result::Result<
+                ::std::option::Option<::ipc_client::__private::json_elem::jsonelem::JsonElem>,
+                ::ipc_client::client::error::Error,
+            > {
+                use ::ipc_client::client::typed;
+                match method {
+                    #(#arms)*
+                    _ => Err(typed::method_not_found()),
+                }
+            }
+
+            fn methods(
+                &self,
+            ) -> ::std::option::Option<::std::vec::Vec<::ipc_client::client::message::MethodDescription>> {
+                use ::ipc_client::client::typed;
+                Some(vec![#(#descriptions),*])
+            }
+        }
+    })
+}
+
+/// Removes the `#[ipc(skip)]` attribute of a method, and tells if it was there.
+fn take_skip(attrs: &mut Vec<Attribute>) -> syn::Result<bool> {
+    let mut skip = false;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("ipc") {
+            return true;
+        }
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown ipc attribute, expected `skip`"))
+            }
+        });
+        if let Err(e) = parsed {
+            result = Err(e);
+        }
+        false
+    });
+    result.map(|_| skip)
+}
+
+/// Returns the remote method of a function that takes `&self`.
+fn method(function: &ImplItemFn) -> syn::Result<Option<Method>> {
+    let sig = &function.sig;
+    match sig.inputs.first() {
+        Some(FnArg::Receiver(receiver))
+            if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+        Some(FnArg::Receiver(receiver)) => {
+            return Err(Error::new_spanned(
+                receiver,
+                "the methods of a shared object take &self, mark the other ones #[ipc(skip)]",
+            ))
+        }
+        _ => return Ok(None),
+    }
+
+    let mut args = Vec::new();
+    for input in sig.inputs.iter().skip(1) {
+        let FnArg::Typed(input) = input else {
+            continue;
+        };
+        let Pat::Ident(pat) = &*input.pat else {
+            return Err(Error::new_spanned(
+                &input.pat,
+                "the arguments of a shared object method must be plain names",
+            ));
+        };
+        if let Type::Reference(ty) = &*input.ty {
+            return Err(Error::new_spanned(
+                ty,
+                "the arguments of a shared object method must be owned",
+            ));
+        }
+        args.push((pat.ident.clone(), (*input.ty).clone()));
+    }
+
+    let ident = &sig.ident;
+    let names = args.iter().map(|(arg, _)| arg);
+    let call = match sig.asyncness {
+        Some(_) => quote!(self.#ident(#(#names),*).await),
+        None => quote!(self.#ident(#(#names),*)),
+    };
+    let (output, fallible) = match &sig.output {
+        ReturnType::Default => (None, false),
+        ReturnType::Type(_, ty) => match result_ok(ty) {
+            Some(ok) => (Some(ok.clone()), true),
+            None => (Some((**ty).clone()), false),
+        },
+    };
+
+    Ok(Some(Method {
+        name: ident.unraw().to_string(),
+        description: description(&function.attrs),
+        args,
+        unit: output.as_ref().is_none_or(is_unit),
+        output,
+        call,
+        fallible,
+    }))
+}
+
+/// Joins the lines of the doc comments.
+fn description(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(doc) if doc.path.is_ident("doc") => match &doc.value {
+                Expr::Lit(lit) => match &lit.lit {
+                    Lit::Str(line) => Some(line.value().trim().to_string()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let description = lines.join("\n").trim().to_string();
+    (!description.is_empty()).then_some(description)
+}
+
+/// Returns the type of the value of a Result.
+fn result_ok(ty: &Type) -> Option<&Type> {
+    let (name, args) = last_segment(ty)?;
+    match (name.as_str(), args.as_slice()) {
+        ("Result", [ok, ..]) => Some(ok),
+        _ => None,
+    }
+}
+
+/// The JSON Schema of the parameter, an object with a field per argument.
+fn params_schema(args: &[(syn::Ident, Type)]) -> String {
+    let properties: Vec<String> = args
+        .iter()
+        .map(|(arg, ty)| {
+            let schema = schema(ty).unwrap_or_else(|| "{}".to_string());
+            format!("{}:{}", json_string(&arg.unraw().to_string()), schema)
+        })
+        .collect();
+    let required: Vec<String> = args
+        .iter()
+        .filter(|(_, ty)| !is_option(ty))
+        .map(|(arg, _)| json_string(&arg.unraw().to_string()))
+        .collect();
+    format!(
+        r#"{{"type":"object","properties":{{{}}},"required":[{}]}}"#,
+        properties.join(","),
+        required.join(",")
+    )
+}
+
+/// The JSON Schema of the values of the type, as far as it can be told from its name.
+/// Returns None for the unit type.
+fn schema(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Tuple(tuple) if tuple.elems.is_empty() => return None,
+        Type::Array(array) => return Some(array_schema(&array.elem)),
+        Type::Slice(slice) => return Some(array_schema(&slice.elem)),
+        Type::Reference(reference) => return schema(&reference.elem),
+        Type::Paren(paren) => return schema(&paren.elem),
+        Type::Group(group) => return schema(&group.elem),
+        _ => {}
+    }
+    let any = "{}".to_string();
+    let Some((name, args)) = last_segment(ty) else {
+        return Some(any);
+    };
+    let kind = |kind: &str| Some(format!(r#"{{"type":"{}"}}"#, kind));
+    match (name.as_str(), args.as_slice()) {
+        ("String" | "str" | "char" | "PathBuf", _) => kind("string"),
+        ("bool", _) => kind("boolean"),
+        (
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+            | "usize",
+            _,
+        ) => kind("integer"),
+        ("f32" | "f64", _) => kind("number"),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [item]) => Some(array_schema(item)),
+        ("HashMap" | "BTreeMap", [_, value]) => Some(format!(
+            r#"{{"type":"object","additionalProperties":{}}}"#,
+            schema(value).unwrap_or(any)
+        )),
+        ("Option", [inner]) => match schema(inner) {
+            // A null is as good as a missing argument.
+            Some(inner) if inner != any => {
+                Some(format!(r#"{{"anyOf":[{},{{"type":"null"}}]}}"#, inner))
+            }
+            _ => Some(any),
+        },
+        ("Box" | "Arc" | "Rc", [inner]) => schema(inner),
+        _ => Some(any),
+    }
+}
+
+fn array_schema(item: &Type) -> String {
+    match schema(item) {
+        Some(item) => format!(r#"{{"type":"array","items":{}}}"#, item),
+        None => r#"{"type":"array"}"#.to_string(),
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+}
+
+fn is_option(ty: &Type) -> bool {
+    matches!(last_segment(ty), Some((name, _)) if name == "Option")
+}
+
+/// The name of the type and its type arguments, as in `Vec` and `[String]` for `Vec<String>`.
+fn last_segment(ty: &Type) -> Option<(String, Vec<&Type>)> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+fn json_string(value: &str) -> String {
+    format!("{:?}", value)
+}
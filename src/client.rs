@@ -1,5 +1,7 @@
 pub mod connector;
 pub mod error;
+pub mod frame;
 pub mod message;
 pub mod shared_object;
+pub mod typed;
 pub mod wait_for_objects;
@@ -1,38 +1,271 @@
+use std::collections::HashMap;
 use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
 
 use json_elem::jsonelem::JsonElem;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::sync::{Mutex, MutexGuard};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, Mutex};
+
+use super::error::{Error, ErrorKind};
+use super::frame::FramedStream;
+use super::message::{
+    CallObjectRequest, Envelope, Event, Hello, IncomingMessage, OutgoingMessage, StaticReplies,
+    SubscribeToEvent, UnsubscribeFromEvent, Welcome, RESERVED_EVENT_PREFIX,
+};
+use super::typed;
+
+use crate::transport::{Address, ReadHalf, Transport, WriteHalf};
 
-use super::error::Error;
-use super::message::{CallObjectRequest, Event, IncomingMessage, StaticReplies, SubscribeToEvent};
+/// The requests waiting for a reply, by request id.
+/// It is None once the connection to the server is closed.
+type PendingReplies = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<IncomingMessage>>>>>;
 
-use crate::{CHUNK_SIZE, ENV_SERVER_ADDRESS, SERVER_ADDRESS};
+/// The channels of the event subscriptions, by event name.
+type EventListeners = Arc<Mutex<HashMap<String, Vec<UnboundedSender<Event>>>>>;
 
 /// An object that is responsible for remote object method calls,
 /// sending events and listening for incoming events.
+///
+/// A background task reads every message coming from the server and routes
+/// replies to their request by id, so the Connector can be cloned and shared
+/// by many tasks with calls in flight concurrently.
+///
+/// Once the server announces that it is shutting down, new requests and the requests
+/// that are left unanswered fail with StaticReplies::ServerShuttingDown.
+///
+/// A request that is not answered within the timeout of the Connector, if it has one,
+/// fails with StaticReplies::CallTimeout.
 #[derive(Clone, Debug)]
 pub struct Connector {
-    socket: Arc<Mutex<TcpStream>>,
+    socket: Arc<Mutex<FramedStream<WriteHalf>>>,
+    pending: PendingReplies,
+    listeners: EventListeners,
+    next_id: Arc<AtomicU64>,
+    shutting_down: Arc<AtomicBool>,
+    timeout: Option<Duration>,
 }
 
 impl Connector {
     /// Connects to the IPC server.
     pub async fn connect() -> Result<Self, Error> {
-        let server_address = std::env::var(ENV_SERVER_ADDRESS).unwrap_or(SERVER_ADDRESS.to_owned());
-        let stream = TcpStream::connect(server_address)
+        let address =
+            Address::from_env().map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+        Self::connect_with(&address).await
+    }
+
+    /// Connects to the IPC server through the transport.
+    pub async fn connect_with(transport: &dyn Transport) -> Result<Self, Error> {
+        let (reader, writer) = transport
+            .connect()
             .await
             .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
 
-        Ok(Self {
-            socket: Arc::new(Mutex::new(stream)),
-        })
+        let connector = Self {
+            socket: Arc::new(Mutex::new(FramedStream::new(writer))),
+            pending: Arc::new(Mutex::new(Some(HashMap::new()))),
+            listeners: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            timeout: None,
+        };
+        tokio::spawn(Self::dispatch(
+            FramedStream::new(reader),
+            connector.pending.clone(),
+            connector.listeners.clone(),
+            connector.shutting_down.clone(),
+        ));
+        Ok(connector)
+    }
+
+    /// Returns a Connector that shares this connection, whose requests fail with
+    /// StaticReplies::CallTimeout when they are not answered within the timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the timeout of the requests, if any.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Reads the messages from the server, hands the replies to the waiting requests
+    /// and the events to their listeners.
+    async fn dispatch(
+        mut socket: FramedStream<ReadHalf>,
+        pending: PendingReplies,
+        listeners: EventListeners,
+        shutting_down: Arc<AtomicBool>,
+    ) {
+        loop {
+            let buf = match socket.read_frame().await {
+                Ok(Some(buf)) => buf,
+                Ok(None) => {
+                    log::error!("{}", StaticReplies::ServerConnectionError);
+                    break;
+                }
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    break;
+                }
+            };
+
+            let envelope = match serde_json::from_slice::<Envelope<IncomingMessage>>(&buf) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    continue;
+                }
+            };
+
+            match (envelope.id, envelope.message) {
+                (_, IncomingMessage::Event(event)) => {
+                    log::trace!("{:?}", &event);
+                    let mut listeners = listeners.lock().await;
+                    if let Some(senders) = listeners.get_mut(&event.event) {
+                        senders.retain(|sender| sender.send(event.clone()).is_ok());
+                    }
+                }
+                (_, IncomingMessage::Shutdown(notice)) => {
+                    log::info!("The server is shutting down in {} ms", notice.deadline_ms);
+                    shutting_down.store(true, Ordering::Relaxed);
+                }
+                (Some(id), msg) => {
+                    let sender = pending.lock().await.as_mut().and_then(|p| p.remove(&id));
+                    match sender {
+                        Some(sender) => sender.send(msg).unwrap_or_else(|msg| {
+                            log::trace!("Request {} is no longer waiting: {:?}", id, msg);
+                        }),
+                        None => log::trace!("Unexpected reply {}: {:?}", id, msg),
+                    }
+                }
+                (None, msg) => {
+                    log::trace!("Unhandled Message: {:?}", msg);
+                }
+            }
+        }
+
+        // Dropping the senders wakes up the requests still waiting for a reply
+        // and ends the subscriptions.
+        pending.lock().await.take();
+        listeners.lock().await.clear();
+    }
+
+    /// Returns true once the server has announced that it is shutting down.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// The error of a request that cannot be answered anymore.
+    fn connection_error(&self) -> Error {
+        let reply = if self.is_shutting_down() {
+            StaticReplies::ServerShuttingDown
+        } else {
+            StaticReplies::RemoteConnectionError
+        };
+        Error::new(JsonElem::String(reply.to_string()))
+    }
+
+    /// Sends a request to the server and waits for the reply with the same id,
+    /// until the timeout expires.
+    async fn request(
+        &self,
+        message: OutgoingMessage,
+        timeout: Option<Duration>,
+    ) -> Result<IncomingMessage, Error> {
+        let (id, rx) = self.send_request(message).await?;
+        self.reply(id, rx, timeout).await
+    }
+
+    /// Sends a request to the server, whose reply is then read from the receiver.
+    async fn send_request(
+        &self,
+        message: OutgoingMessage,
+    ) -> Result<(u64, oneshot::Receiver<IncomingMessage>), Error> {
+        if self.is_shutting_down() {
+            return Err(self.connection_error());
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        match self.pending.lock().await.as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => return Err(self.connection_error()),
+        };
+
+        if let Err(e) = self.send(Envelope::new(message).with_id(Some(id))).await {
+            if let Some(pending) = self.pending.lock().await.as_mut() {
+                pending.remove(&id);
+            }
+            return Err(e);
+        }
+        Ok((id, rx))
+    }
+
+    /// Waits for the reply of the request sent with send_request, until the timeout expires.
+    async fn reply(
+        &self,
+        id: u64,
+        rx: oneshot::Receiver<IncomingMessage>,
+        timeout: Option<Duration>,
+    ) -> Result<IncomingMessage, Error> {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return rx.await.map_err(|_| self.connection_error()),
+        };
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(reply) => reply.map_err(|_| self.connection_error()),
+            Err(_) => {
+                // A late reply is dropped by the dispatch task.
+                if let Some(pending) = self.pending.lock().await.as_mut() {
+                    pending.remove(&id);
+                }
+                Err(Error::new(JsonElem::String(
+                    StaticReplies::CallTimeout.to_string(),
+                )))
+            }
+        }
+    }
+
+    /// Writes a message to the server.
+    async fn send(&self, message: Envelope<OutgoingMessage>) -> Result<(), Error> {
+        let data = message
+            .serialize()
+            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+
+        self.socket
+            .lock()
+            .await
+            .write_frame(data.as_slice())
+            .await
+            .map_err(|e| Error::new(JsonElem::String(e.to_string())))
+    }
+
+    /// Introduces the client to the server with an optional display name for its logs,
+    /// and returns the id the server gave to this connection.
+    pub async fn hello(&self, name: Option<&str>) -> Result<Welcome, Error> {
+        match self
+            .request(OutgoingMessage::Hello(Hello::new(name)), self.timeout)
+            .await?
+        {
+            IncomingMessage::Welcome(welcome) => Ok(welcome),
+            IncomingMessage::Error(err) => Err(err),
+            _ => Err(Error::new(JsonElem::String(
+                StaticReplies::InvalidResponseData.to_string(),
+            ))),
+        }
     }
 
     /// Calls shared object methods from other processes.
     /// It has an optional parameters, the value is in JsonElem type.
+    /// A method that returned null, like None, answers "OK".
     pub async fn remote_call(
         &self,
         object: &str,
@@ -40,65 +273,171 @@ impl Connector {
         param: Option<JsonElem>,
     ) -> Result<JsonElem, Error> {
         let request = CallObjectRequest::new(object, method, param);
+        let response = self.call_object(request, self.timeout).await?;
+        Ok(typed::null_as_ok(response))
+    }
 
-        let mut socket = self.socket.lock().await;
+    /// Calls a shared object method like remote_call, where a method that returned null
+    /// answers None instead of "OK".
+    pub async fn remote_call_value(
+        &self,
+        object: &str,
+        method: &str,
+        param: Option<JsonElem>,
+    ) -> Result<Option<JsonElem>, Error> {
+        let request = CallObjectRequest::new(object, method, param);
+        self.call_object(request, self.timeout).await
+    }
 
-        socket
-            .write_all(
-                request
-                    .serialize()
-                    .map_err(|e| Error::new(JsonElem::String(e.to_string())))?
-                    .as_slice(),
-            )
-            .await
-            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+    /// Calls a shared object method like remote_call, but fails with
+    /// StaticReplies::CallTimeout if it is not answered within the timeout.
+    /// The server is told about the timeout, so it stops waiting for the response as well.
+    pub async fn remote_call_with_timeout(
+        &self,
+        object: &str,
+        method: &str,
+        param: Option<JsonElem>,
+        timeout: Duration,
+    ) -> Result<JsonElem, Error> {
+        let request = CallObjectRequest::new(object, method, param);
+        let response = self.call_object(request, Some(timeout)).await?;
+        Ok(typed::null_as_ok(response))
+    }
 
-        let mut buf = Vec::new();
-        let n = read(&mut socket, &mut buf)
-            .await
-            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+    /// Calls a shared object method with a parameter and a result of serde types.
+    /// A parameter that serializes to null, like `()` or None, is left out. A result of null
+    /// is read as null, and "OK", the result of a method without a value, is read as null
+    /// only if R cannot take the string, like `()`.
+    /// A result that does not deserialize into R fails with ErrorKind::Deserialize.
+    pub async fn call<P: Serialize + ?Sized, R: DeserializeOwned>(
+        &self,
+        object: &str,
+        method: &str,
+        param: &P,
+    ) -> Result<R, Error> {
+        let param = typed::to_elem(param)?;
+        let response = self.remote_call_value(object, method, param).await?;
+        typed::from_response(response)
+    }
 
-        if n == 0 {
-            Err(Error::new(JsonElem::String(
-                StaticReplies::RemoteConnectionError.to_string(),
-            )))
-        } else {
-            let result: IncomingMessage = serde_json::from_slice(&buf[0..n])
-                .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
-            if let IncomingMessage::CallResponse(response) = result {
+    /// Sends the call request and waits for its response until the timeout expires.
+    async fn call_object(
+        &self,
+        request: CallObjectRequest,
+        timeout: Option<Duration>,
+    ) -> Result<Option<JsonElem>, Error> {
+        let request = match timeout {
+            Some(timeout) => request.with_timeout(timeout),
+            None => request,
+        };
+
+        match self
+            .request(OutgoingMessage::CallRequest(request), timeout)
+            .await?
+        {
+            IncomingMessage::CallResponse(response) => {
                 log::trace!("Response: {:?}", response);
-                Ok(response.response)
-            } else if let IncomingMessage::Error(err) = result {
-                Err(err)
-            } else {
-                Err(Error::new(JsonElem::String(
-                    StaticReplies::InvalidResponseData.to_string(),
-                )))
+                Ok(response.value())
             }
+            IncomingMessage::Error(err) => Err(err),
+            _ => Err(Error::new(JsonElem::String(
+                StaticReplies::InvalidResponseData.to_string(),
+            ))),
         }
     }
 
     /// Sends the event to the ipc-server and let the ipc-server
     /// boadcast the message to all subscribed processes.
     /// Parameters in JsonElem type.
+    /// The event is not acknowledged, and the events reserved to the server fail with
+    /// StaticReplies::ReservedEvent without being sent.
     pub async fn send_event(&self, event: &str, result: JsonElem) -> Result<(), Error> {
+        if event.starts_with(RESERVED_EVENT_PREFIX) {
+            return Err(Error::new(JsonElem::String(
+                StaticReplies::ReservedEvent.to_string(),
+            )));
+        }
         let request = Event::new(event, result);
+        self.send(Envelope::new(OutgoingMessage::SendEvent(request)))
+            .await
+    }
 
-        let mut socket = self.socket.lock().await;
+    /// Sends the event like send_event, with a result of a serde type.
+    /// A result that serializes to null fails with ErrorKind::Serialize, as events carry a value.
+    pub async fn send_event_typed<T: Serialize + ?Sized>(
+        &self,
+        event: &str,
+        result: &T,
+    ) -> Result<(), Error> {
+        let result = typed::to_elem(result)?.ok_or_else(|| {
+            Error::new(JsonElem::String("an event cannot be null".to_string()))
+                .with_kind(ErrorKind::Serialize)
+        })?;
+        self.send_event(event, result).await
+    }
+
+    /// Subscribes to the event, whose occurrences are read from the returned Subscription.
+    /// The events are routed to every subscription of the event by the background task that
+    /// reads the messages from the server, so a Connector can have many subscriptions and
+    /// calls in flight at the same time.
+    ///
+    /// Dropping the Subscription unsubscribes, the server is told once the last subscription
+    /// of the event is dropped. The Subscription ends when the connection to the server is closed.
+    ///
+    /// It returns once the server has the subscription, so the events sent from then on are received.
+    pub async fn subscribe(&self, event_name: &str) -> Result<Subscription, Error> {
+        let (tx, rx) = unbounded_channel();
+        let mut listeners = self.listeners.lock().await;
+        listeners
+            .entry(event_name.to_string())
+            .or_default()
+            .push(tx);
+        let subscription = Subscription {
+            event: event_name.to_string(),
+            receiver: rx,
+            connector: self.clone(),
+        };
 
-        socket
-            .write_all(
-                request
-                    .serialize()
-                    .map_err(|e| Error::new(JsonElem::String(e.to_string())))?
-                    .as_slice(),
-            )
+        // The listeners stay locked until the request is sent, so the subscription cannot be
+        // overtaken by the unsubscription of a Subscription of the same event being dropped.
+        // They are unlocked before the reply, which may come after events to dispatch.
+        let request = SubscribeToEvent::new(event_name);
+        let (id, rx) = self
+            .send_request(OutgoingMessage::SubscribeEvent(request))
+            .await?;
+        drop(listeners);
+        // The subscription is dropped, and so undone, if the server does not take it.
+        match self.reply(id, rx, self.timeout).await? {
+            IncomingMessage::Error(err) => Err(err),
+            _ => Ok(subscription),
+        }
+    }
+
+    /// Forgets the dropped subscriptions of the event, and tells the server once none is left.
+    async fn unsubscribe(&self, event_name: &str) {
+        let mut listeners = self.listeners.lock().await;
+        match listeners.get_mut(event_name) {
+            Some(senders) => {
+                senders.retain(|sender| !sender.is_closed());
+                if !senders.is_empty() {
+                    return;
+                }
+                listeners.remove(event_name);
+            }
+            None => return,
+        }
+
+        let request = UnsubscribeFromEvent::new(event_name);
+        if let Err(e) = self
+            .send(Envelope::new(OutgoingMessage::UnsubscribeEvent(request)))
             .await
-            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
-        Ok(())
+        {
+            log::trace!("Cannot unsubscribe from {}: {}", event_name, e);
+        }
     }
 
     /// Subscribes and listens for incoming events from other processes.
+    /// The listener unsubscribes when the callback fails.
     pub async fn listen_for_event<
         F: Future<Output = Result<(), RE>> + Send,
         RE: std::error::Error + 'static + Send,
@@ -108,75 +447,90 @@ impl Connector {
         event_name: &str,
         callback: T,
     ) -> Result<(), Error> {
-        let request = SubscribeToEvent::new(event_name);
-
-        let mut socket = self.socket.lock().await;
-
-        socket
-            .write_all(
-                request
-                    .serialize()
-                    .map_err(|e| Error::new(JsonElem::String(e.to_string())))?
-                    .as_slice(),
-            )
-            .await
-            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
-
-        let socket = self.socket.clone();
-
+        // The subscription keeps the connection open for as long as the listener is running.
+        let mut subscription = self.subscribe(event_name).await?;
         tokio::spawn(async move {
-            loop {
-                let mut socket = socket.lock().await;
-
-                let mut buf = Vec::new();
-                let n = read(&mut socket, &mut buf)
-                    .await
-                    .map_err(|e| Error::new(JsonElem::String(e.to_string())))
-                    .unwrap_or_else(|e| {
-                        log::error!("{:?}", e);
-                        0
-                    });
-
-                let value: Event = serde_json::from_slice(&buf[0..n]).unwrap();
-
-                log::trace!("{:?}", &value);
-
-                match callback(value.result).await {
-                    Ok(_) => {
-                        tokio::task::yield_now().await;
-                        continue;
-                    }
-                    Err(err) => {
-                        log::error!("{err:?}");
-                        break;
-                    }
+            while let Some(event) = subscription.recv().await {
+                if let Err(err) = callback(event.result).await {
+                    log::error!("{err:?}");
+                    break;
                 }
             }
         });
         Ok(())
     }
-}
 
-pub async fn read(
-    socket: &mut MutexGuard<'_, TcpStream>,
-    data: &mut Vec<u8>,
-) -> std::io::Result<usize> {
-    loop {
-        let mut buffer = [0u8; CHUNK_SIZE];
-        match socket.read(&mut buffer).await {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    return Ok(bytes_read);
+    /// Subscribes and listens for incoming events like listen_for_event, with results of a serde type.
+    /// The events whose result does not deserialize into T are logged and skipped.
+    pub async fn listen_for_event_typed<
+        T: DeserializeOwned + 'static,
+        F: Future<Output = Result<(), RE>> + Send + 'static,
+        RE: std::error::Error + 'static + Send,
+        C: Fn(T) -> F + Send + Sync + 'static,
+    >(
+        &self,
+        event_name: &str,
+        callback: C,
+    ) -> Result<(), Error> {
+        let event = event_name.to_string();
+        self.listen_for_event(event_name, move |result| {
+            let future = match typed::from_elem(&result) {
+                Ok(result) => Some(callback(result)),
+                Err(e) => {
+                    log::error!("Invalid result of event {}: {}", event, e);
+                    None
                 }
-                data.extend_from_slice(&buffer[0..bytes_read]);
-
-                if bytes_read < CHUNK_SIZE {
-                    return Ok(data.len());
+            };
+            async move {
+                match future {
+                    Some(future) => future.await,
+                    None => Ok(()),
                 }
             }
-            Err(e) => {
-                return Err(e);
-            }
+        })
+        .await
+    }
+}
+
+/// The occurrences of an event the Connector subscribed to, as a Stream.
+/// It keeps the connection open, and dropping it unsubscribes.
+#[derive(Debug)]
+pub struct Subscription {
+    event: String,
+    receiver: UnboundedReceiver<Event>,
+    connector: Connector,
+}
+
+impl Subscription {
+    /// Returns the name of the event.
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    /// Waits for the next occurrence of the event.
+    /// Returns None once the connection to the server is closed.
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Closed, the sender of the subscription is told apart from the other ones of the event.
+        self.receiver.close();
+        let connector = self.connector.clone();
+        let event = std::mem::take(&mut self.event);
+        // Without a runtime, the connection is going away anyway.
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move { connector.unsubscribe(&event).await });
         }
     }
 }
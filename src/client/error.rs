@@ -3,16 +3,49 @@ use std::fmt::Display;
 use json_elem::jsonelem::JsonElem;
 use serde_derive::{Deserialize, Serialize};
 
+/// The kind of an Error, told apart on the client side only.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An error of the server, of the remote object or of the connection.
+    #[default]
+    Other,
+    /// A value could not be serialized into JsonElem.
+    Serialize,
+    /// A JsonElem could not be deserialized into the expected type.
+    Deserialize,
+}
+
 /// An object that is responsible to house error in JsonElem type
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Error {
     error: JsonElem,
+    #[serde(skip)]
+    kind: ErrorKind,
 }
 
 impl Error {
     /// Creates an Error object in JsonElem
     pub fn new(error: JsonElem) -> Self {
-        Self { error }
+        Self {
+            error,
+            kind: ErrorKind::default(),
+        }
+    }
+
+    /// Sets the kind of the error.
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the error in JsonElem.
+    pub fn error(&self) -> &JsonElem {
+        &self.error
     }
 }
 
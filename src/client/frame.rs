@@ -0,0 +1,282 @@
+use std::io::{Error, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{CHUNK_SIZE, MAX_FRAME_SIZE};
+
+/// The size of the length prefix that precedes every frame.
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+/// The way messages are delimited on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Every message is preceded by its length as a 4-byte big-endian integer.
+    LengthPrefixed,
+    /// Bare JSON documents written back to back, as sent by clients before framing existed.
+    Legacy,
+}
+
+/// A stream that reads and writes whole messages instead of raw bytes.
+///
+/// Bytes that arrive past the end of a frame are kept for the next read, so
+/// coalesced writes and messages split across several TCP segments are
+/// delivered intact.
+#[derive(Debug)]
+pub struct FramedStream<S> {
+    stream: S,
+    buffer: Vec<u8>,
+    framing: Option<Framing>,
+    max_frame_size: usize,
+}
+
+impl<S> FramedStream<S> {
+    /// Creates a FramedStream that uses length-prefixed framing.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            framing: Some(Framing::LengthPrefixed),
+            max_frame_size: MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Creates a FramedStream that detects the framing from the first byte received.
+    /// A legacy client always starts with a JSON object, while the length prefix of
+    /// a frame no larger than MAX_FRAME_SIZE never starts with `{`.
+    pub fn detect(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            framing: None,
+            max_frame_size: MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Creates a FramedStream with a known framing, for instance the write half of a
+    /// connection whose framing was detected on its read half.
+    pub fn with_framing(stream: S, framing: Framing) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            framing: Some(framing),
+            max_frame_size: MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Sets the size above which a frame is rejected, MAX_FRAME_SIZE by default.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Returns the framing of this connection, or None if it is not detected yet.
+    pub fn framing(&self) -> Option<Framing> {
+        self.framing
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Takes the next complete frame out of the buffer, if there is one.
+    fn decode(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let framing = match self.framing {
+            Some(framing) => framing,
+            None => match self.buffer.first() {
+                Some(b'{') => *self.framing.insert(Framing::Legacy),
+                Some(_) => *self.framing.insert(Framing::LengthPrefixed),
+                None => return Ok(None),
+            },
+        };
+
+        match framing {
+            Framing::LengthPrefixed => {
+                if self.buffer.len() < FRAME_HEADER_SIZE {
+                    return Ok(None);
+                }
+                let mut header = [0u8; FRAME_HEADER_SIZE];
+                header.copy_from_slice(&self.buffer[..FRAME_HEADER_SIZE]);
+                let length = u32::from_be_bytes(header) as usize;
+                if length > self.max_frame_size {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("frame of {} bytes exceeds the limit", length),
+                    ));
+                }
+                if self.buffer.len() < FRAME_HEADER_SIZE + length {
+                    return Ok(None);
+                }
+                let frame = self.buffer[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + length].to_vec();
+                self.buffer.drain(..FRAME_HEADER_SIZE + length);
+                Ok(Some(frame))
+            }
+            Framing::Legacy => {
+                let start = match self.buffer.iter().position(|b| !b.is_ascii_whitespace()) {
+                    Some(start) => start,
+                    None => {
+                        self.buffer.clear();
+                        return Ok(None);
+                    }
+                };
+                let mut values = serde_json::Deserializer::from_slice(&self.buffer[start..])
+                    .into_iter::<serde::de::IgnoredAny>();
+                let end = match values.next() {
+                    Some(Ok(_)) => start + values.byte_offset(),
+                    Some(Err(e)) if e.is_eof() => return Ok(None),
+                    // There is no way to find the next message after invalid JSON, so
+                    // hand everything over and let the receiver report the parse error.
+                    Some(Err(_)) => self.buffer.len(),
+                    None => return Ok(None),
+                };
+                let frame = self.buffer[start..end].to_vec();
+                self.buffer.drain(..end);
+                Ok(Some(frame))
+            }
+        }
+    }
+
+    /// Handles the end of the stream, which is only clean between frames.
+    fn eof(&self) -> std::io::Result<Option<Vec<u8>>> {
+        if self.buffer.iter().all(|b| b.is_ascii_whitespace()) {
+            Ok(None)
+        } else {
+            Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed in the middle of a frame",
+            ))
+        }
+    }
+
+    /// Wraps the payload according to the framing of this connection.
+    fn encode(&self, payload: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self.framing.unwrap_or(Framing::LengthPrefixed) {
+            Framing::LengthPrefixed => {
+                if payload.len() > self.max_frame_size {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("frame of {} bytes exceeds the limit", payload.len()),
+                    ));
+                }
+                let mut data = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+                data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                data.extend_from_slice(payload);
+                Ok(data)
+            }
+            Framing::Legacy => Ok(payload.to_vec()),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> FramedStream<S> {
+    /// Reads the next frame. Returns None if the peer has closed the connection.
+    /// This is cancel safe, partially received frames are kept in the buffer.
+    pub async fn read_frame(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(frame) = self.decode()? {
+                return Ok(Some(frame));
+            }
+            self.buffer.reserve(CHUNK_SIZE);
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return self.eof();
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> FramedStream<S> {
+    /// Writes the payload as a single frame.
+    pub async fn write_frame(&mut self, payload: &[u8]) -> std::io::Result<()> {
+        let data = self.encode(payload)?;
+        self.stream.write_all(&data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use crate::client::frame::{FramedStream, Framing};
+    use crate::CHUNK_SIZE;
+
+    #[tokio::test]
+    async fn test_exact_chunk_size_frame() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = FramedStream::new(client);
+        let mut server = FramedStream::detect(server);
+
+        let payload = vec![b'a'; CHUNK_SIZE];
+        let expected = payload.clone();
+        tokio::spawn(async move { client.write_frame(&payload).await.unwrap() });
+
+        assert_eq!(server.read_frame().await.unwrap(), Some(expected));
+        assert_eq!(server.framing(), Some(Framing::LengthPrefixed));
+        assert_eq!(server.read_frame().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_and_split_frames() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut server = FramedStream::detect(server);
+
+        let mut data = Vec::new();
+        for payload in [&br#"{"success":"OK"}"#[..], &br#"{"list":[]}"#[..]] {
+            data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            data.extend_from_slice(payload);
+        }
+        tokio::spawn(async move {
+            // The first write holds a partial header, the second one ends in the middle
+            // of the second frame.
+            client.write_all(&data[..2]).await.unwrap();
+            client.write_all(&data[2..30]).await.unwrap();
+            client.write_all(&data[30..]).await.unwrap();
+        });
+
+        assert_eq!(
+            server.read_frame().await.unwrap(),
+            Some(br#"{"success":"OK"}"#.to_vec())
+        );
+        assert_eq!(
+            server.read_frame().await.unwrap(),
+            Some(br#"{"list":[]}"#.to_vec())
+        );
+        assert_eq!(server.read_frame().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_framing() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut server = FramedStream::detect(server);
+
+        tokio::spawn(async move {
+            client
+                .write_all(br#"{"reg_object":"mango"}{"list":["ma"#)
+                .await
+                .unwrap();
+            client.write_all(br#"ngo"]} "#).await.unwrap();
+        });
+
+        assert_eq!(
+            server.read_frame().await.unwrap(),
+            Some(br#"{"reg_object":"mango"}"#.to_vec())
+        );
+        assert_eq!(server.framing(), Some(Framing::Legacy));
+        assert_eq!(
+            server.read_frame().await.unwrap(),
+            Some(br#"{"list":["mango"]}"#.to_vec())
+        );
+        assert_eq!(server.read_frame().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_frame() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut server = FramedStream::detect(server);
+
+        tokio::spawn(async move {
+            client.write_all(&[0, 0, 0, 10, b'{']).await.unwrap();
+        });
+
+        let err = server.read_frame().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,14 +1,140 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use json_elem::jsonelem::JsonElem;
 use serde_derive::{Deserialize, Serialize};
 use strum_macros::{AsRefStr, Display, EnumString};
 
 use super::error::Error;
 
+/// The version of the message envelope spoken by this library.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The events whose name starts with this prefix are sent by the IPC server itself.
+pub const RESERVED_EVENT_PREFIX: &str = "ipc.";
+/// The event sent by the IPC server when an object is registered. Its result holds
+/// the name of the `object` and the client that shares it, its `owner`.
+pub const EVENT_OBJECT_REGISTERED: &str = "ipc.object_registered";
+/// The event sent by the IPC server when an object is no longer shared, for instance
+/// because its owner disconnected. Its result is the same as EVENT_OBJECT_REGISTERED.
+pub const EVENT_OBJECT_UNREGISTERED: &str = "ipc.object_unregistered";
+/// The objects whose name starts with this prefix are shared by the IPC server itself.
+pub const RESERVED_OBJECT_PREFIX: &str = "ipc.";
+/// The object of the IPC server that tells about the registered objects, the connected
+/// clients and the event subscriptions. Its methods are `list_objects`, `list_clients`,
+/// `list_subscriptions` and `describe_object`, which takes the name of the `object`.
+pub const SERVER_OBJECT: &str = "ipc.server";
+
+/// An object that wraps every message exchanged with the IPC server.
+/// The kind of message is stored in the `type` field and the message itself
+/// in the `payload` field, next to the metadata of the message.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Envelope<T> {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    #[serde(flatten)]
+    pub message: T,
+}
+
+impl<T> Envelope<T> {
+    /// Creates an Envelope of the current protocol version around the message.
+    pub fn new(message: T) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            id: None,
+            headers: HashMap::new(),
+            message,
+        }
+    }
+    /// Sets the id that correlates a request with its reply.
+    pub fn with_id(mut self, id: Option<u64>) -> Self {
+        self.id = id;
+        self
+    }
+}
+
+impl<T: serde::Serialize> Envelope<T> {
+    /// Converts this object into JSON bytes stream.
+    pub fn serialize(self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(&self)
+    }
+}
+
+/// What the IPC server does when the object is already registered by another client.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterPolicy {
+    /// The registration fails with StaticReplies::AlreadyRegistered.
+    #[default]
+    Reject,
+    /// The object is taken over from the other client.
+    Replace,
+    /// The client is queued, and takes the object over once every client
+    /// queued before it and the current owner are gone.
+    Standby,
+}
+
+impl RegisterPolicy {
+    fn is_default(&self) -> bool {
+        *self == RegisterPolicy::default()
+    }
+}
+
+/// JSON format to describe a method of a shared object, with the JSON Schema
+/// of its parameter and of its result.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MethodDescription {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The JSON Schema of the parameter, which is null when the method takes none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<JsonElem>,
+    /// The JSON Schema of the result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<JsonElem>,
+}
+
+impl MethodDescription {
+    /// Creates a MethodDescription of the method, whose parameter and result are not described.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: None,
+            params: None,
+            result: None,
+        }
+    }
+    /// Sets the description of the method, for the people calling it.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+    /// Sets the JSON Schema of the parameter.
+    pub fn with_params(mut self, schema: JsonElem) -> Self {
+        self.params = Some(schema);
+        self
+    }
+    /// Sets the JSON Schema of the result.
+    pub fn with_result(mut self, schema: JsonElem) -> Self {
+        self.result = Some(schema);
+        self
+    }
+}
+
 /// An object that is responsible in building an object registration
 /// protocol stream in JSON format.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RegisterObject {
     pub reg_object: String,
+    #[serde(default, skip_serializing_if = "RegisterPolicy::is_default")]
+    pub policy: RegisterPolicy,
+    /// The methods of the object, if it describes them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub methods: Option<Vec<MethodDescription>>,
 }
 
 impl RegisterObject {
@@ -17,14 +143,43 @@ impl RegisterObject {
     pub fn new(name: &str) -> Self {
         Self {
             reg_object: name.to_string(),
+            policy: RegisterPolicy::default(),
+            methods: None,
         }
     }
+    /// Sets what the IPC server does when the object is already registered by another client.
+    pub fn with_policy(mut self, policy: RegisterPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    /// Sets the methods of the object, which the IPC server tells to the clients that
+    /// describe the object, and may check the calls against.
+    pub fn with_methods(mut self, methods: Option<Vec<MethodDescription>>) -> Self {
+        self.methods = methods;
+        self
+    }
     /// Converts this object into JSON bytes stream.
     pub fn serialize(self) -> Result<Vec<u8>, Error> {
         serde_json::to_vec(&self).map_err(|e| Error::new(JsonElem::String(e.to_string())))
     }
 }
 
+/// An object that removes a registered object from the IPC server,
+/// while the connection and the other objects stay registered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnregisterObject {
+    pub unreg_object: String,
+}
+
+impl UnregisterObject {
+    /// Creates an UnregisterObject with the name of the object to remove.
+    pub fn new(name: &str) -> Self {
+        Self {
+            unreg_object: name.to_string(),
+        }
+    }
+}
+
 /// An object that is return after a successful registration,
 /// successful subscription for listening events.
 #[derive(Serialize, Deserialize, Debug)]
@@ -52,6 +207,10 @@ pub struct CallObjectRequest {
     pub method: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub param: Option<JsonElem>,
+    /// The time in milliseconds the caller waits for the response, instead of
+    /// the default call timeout of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
 }
 
 impl CallObjectRequest {
@@ -61,8 +220,14 @@ impl CallObjectRequest {
             object: object.to_string(),
             method: method.to_string(),
             param,
+            timeout_ms: None,
         }
     }
+    /// Sets the time the server waits for the response before failing the call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = Some(timeout.as_millis() as u64);
+        self
+    }
     /// Converts this object into JSON bytes stream.
     pub fn serialize(self) -> Result<Vec<u8>, serde_json::Error> {
         serde_json::to_vec(&self)
@@ -71,15 +236,35 @@ impl CallObjectRequest {
 
 /// An object that is responsible in building a response protocol
 /// of CallObjectRequest is JSON format.
+/// A method that returned null, like None, which JsonElem cannot hold, answers "OK" with
+/// the `null` flag set, so the clients that do not know the flag still read "OK".
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CallObjectResponse {
     pub response: JsonElem,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub null: bool,
 }
 
 impl CallObjectResponse {
     /// Creates a new CallObjectResponse object.
     pub fn new(response: JsonElem) -> Self {
-        Self { response }
+        Self {
+            response,
+            null: false,
+        }
+    }
+
+    /// Creates the response of a method that returned null.
+    pub fn null() -> Self {
+        Self {
+            response: JsonElem::String(StaticReplies::Ok.to_string()),
+            null: true,
+        }
+    }
+
+    /// Returns the response, None if the method returned null.
+    pub fn value(self) -> Option<JsonElem> {
+        (!self.null).then_some(self.response)
     }
     /// Converts this object into JSON bytes stream.
     pub fn serialize(self) -> Result<Vec<u8>, serde_json::Error> {
@@ -129,6 +314,21 @@ impl SubscribeToEvent {
     }
 }
 
+/// An object that cancels the subscription of the client to an event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnsubscribeFromEvent {
+    pub event_name: String,
+}
+
+impl UnsubscribeFromEvent {
+    /// Creates an UnsubscribeFromEvent with the name of the event.
+    pub fn new(event_name: &str) -> Self {
+        Self {
+            event_name: event_name.to_string(),
+        }
+    }
+}
+
 /// An object that is responsible in building a list of objects in
 /// JSON format to determine if this objects are already available
 /// for communication in the IPC server to other processes.
@@ -144,32 +344,106 @@ impl ListObjects {
     }
 }
 
+/// JSON format to introduce the client to the IPC server, optionally with a display name
+/// that shows up in the logs of the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Hello {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Hello {
+    /// Creates a new Hello object.
+    pub fn new(name: Option<&str>) -> Self {
+        Self {
+            name: name.map(str::to_string),
+        }
+    }
+}
+
+/// The reply to a Hello, with the id the IPC server gave to the connection when it
+/// accepted it. Registry events name the owner of an object by this id.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Welcome {
+    pub client_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Welcome {
+    /// Creates a new Welcome object.
+    pub fn new(client_id: u64, name: Option<String>) -> Self {
+        Self { client_id, name }
+    }
+}
+
+/// A notice that the IPC server is shutting down. The calls in flight are still answered
+/// until the deadline, then the connection is closed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShutdownNotice {
+    /// The milliseconds the server waits for the calls in flight before closing the connection.
+    pub deadline_ms: u64,
+}
+
+impl ShutdownNotice {
+    /// Creates a new ShutdownNotice object.
+    pub fn new(deadline_ms: u64) -> Self {
+        Self { deadline_ms }
+    }
+}
+
 /// A list of possible incoming messages to the client.
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
+#[serde(tag = "type", content = "payload")]
 pub enum IncomingMessage {
+    #[serde(rename = "success")]
     Register(Success),
+    #[serde(rename = "error")]
     Error(Error),
+    #[serde(rename = "call_request")]
     CallRequest(CallObjectRequest),
+    #[serde(rename = "call_response")]
     CallResponse(CallObjectResponse),
+    #[serde(rename = "list_objects")]
     WaitForObjects(ListObjects),
+    #[serde(rename = "event")]
+    Event(Event),
+    #[serde(rename = "shutdown")]
+    Shutdown(ShutdownNotice),
+    #[serde(rename = "welcome")]
+    Welcome(Welcome),
 }
 
 /// A list of possible outgoing messages from the client.
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
+#[serde(tag = "type", content = "payload")]
 pub enum OutgoingMessage {
+    #[serde(rename = "register_object")]
+    Register(RegisterObject),
+    #[serde(rename = "unregister_object")]
+    Unregister(UnregisterObject),
+    #[serde(rename = "error")]
     Error(Error),
+    #[serde(rename = "call_request")]
+    CallRequest(CallObjectRequest),
+    #[serde(rename = "call_response")]
     CallResponse(CallObjectResponse),
+    #[serde(rename = "list_objects")]
     WaitForObjects(ListObjects),
+    #[serde(rename = "event")]
     SendEvent(Event),
+    #[serde(rename = "subscribe_to_event")]
     SubscribeEvent(SubscribeToEvent),
+    #[serde(rename = "unsubscribe_from_event")]
+    UnsubscribeEvent(UnsubscribeFromEvent),
+    #[serde(rename = "hello")]
+    Hello(Hello),
 }
 
 impl OutgoingMessage {
-    /// Converts this object into JSON bytes stream.
+    /// Converts this object into JSON bytes stream wrapped in an Envelope.
     pub fn serialize(self) -> Result<Vec<u8>, serde_json::Error> {
-        serde_json::to_vec(&self)
+        Envelope::new(self).serialize()
     }
 }
 
@@ -190,4 +464,69 @@ pub enum StaticReplies {
     RemoteConnectionError,
     #[strum(serialize = "invalid response data")]
     InvalidResponseData,
+    #[strum(serialize = "unsupported protocol version")]
+    UnsupportedVersion,
+    #[strum(serialize = "server is shutting down")]
+    ServerShuttingDown,
+    #[strum(serialize = "call timed out")]
+    CallTimeout,
+    #[strum(serialize = "event name is reserved")]
+    ReservedEvent,
+    #[strum(serialize = "object is registered by another client")]
+    NotOwner,
+    #[strum(serialize = "object is already registered")]
+    AlreadyRegistered,
+    #[strum(serialize = "standby")]
+    Standby,
+    #[strum(serialize = "object name is reserved")]
+    ReservedObject,
+    #[strum(serialize = "method not found")]
+    MethodNotFound,
+    #[strum(serialize = "invalid parameter")]
+    InvalidParameter,
+    #[strum(serialize = "object is replaced by another client")]
+    ObjectReplaced,
+}
+
+#[cfg(test)]
+mod tests {
+    use json_elem::jsonelem::JsonElem;
+
+    use crate::client::message::{
+        CallObjectResponse, Envelope, IncomingMessage, OutgoingMessage, PROTOCOL_VERSION,
+    };
+
+    #[test]
+    fn test_envelope() {
+        let mut response = std::collections::HashMap::new();
+        response.insert("list".to_string(), JsonElem::Vec(Vec::new()));
+        let msg = OutgoingMessage::CallResponse(CallObjectResponse::new(JsonElem::HashMap(
+            response.clone(),
+        )));
+        let data = Envelope::new(msg).with_id(Some(42)).serialize().unwrap();
+
+        // A response that contains a `list` key is still a call response.
+        let envelope: Envelope<IncomingMessage> = serde_json::from_slice(&data).unwrap();
+        assert_eq!(envelope.version, PROTOCOL_VERSION);
+        assert_eq!(envelope.id, Some(42));
+        match envelope.message {
+            IncomingMessage::CallResponse(msg) => {
+                assert_eq!(msg.value(), Some(JsonElem::HashMap(response)))
+            }
+            msg => panic!("This must be IncomingMessage::CallResponse: {:?}", msg),
+        }
+
+        // A response of null is told apart from every value.
+        let data = Envelope::new(OutgoingMessage::CallResponse(CallObjectResponse::null()))
+            .serialize()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&data).contains(r#""response":"OK","null":true"#));
+        match serde_json::from_slice::<Envelope<IncomingMessage>>(&data)
+            .unwrap()
+            .message
+        {
+            IncomingMessage::CallResponse(msg) => assert_eq!(msg.value(), None),
+            msg => panic!("This must be IncomingMessage::CallResponse: {:?}", msg),
+        }
+    }
 }
@@ -1,152 +1,380 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{collections::HashMap, sync::Arc};
 
 use async_trait::async_trait;
 use json_elem::jsonelem::JsonElem;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex};
 use tokio::task::JoinHandle;
 
 use crate::client::message::CallObjectResponse;
-use crate::{ENV_SERVER_ADDRESS, SERVER_ADDRESS};
+use crate::transport::{Address, ReadHalf, Transport, WriteHalf};
 
-use super::connector::read;
 use super::error::Error;
-use super::message::{IncomingMessage, OutgoingMessage, RegisterObject, StaticReplies};
+use super::frame::FramedStream;
+use super::message::{
+    Envelope, Hello, IncomingMessage, MethodDescription, OutgoingMessage, RegisterObject,
+    RegisterPolicy, StaticReplies, UnregisterObject, Welcome, EVENT_OBJECT_UNREGISTERED,
+};
+
+/// The requests waiting for a reply once the dispatcher is spawned, by request id.
+/// It is None once the connection to the server is closed.
+type PendingReplies = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<IncomingMessage>>>>>;
 
 /// A trait to be implemented by an application that wants to share the object
 /// to the IPC server for remote call method calls from other processes.
 #[async_trait]
 pub trait SharedObject: Send + Sync + 'static {
     async fn remote_call(&self, method: &str, param: Option<JsonElem>) -> Result<JsonElem, Error>;
+
+    /// Calls the method like remote_call, where None stands for a value of null, like the
+    /// None of an Option, which JsonElem cannot hold. The callers then tell it apart from "OK".
+    async fn remote_call_value(
+        &self,
+        method: &str,
+        param: Option<JsonElem>,
+    ) -> Result<Option<JsonElem>, Error> {
+        self.remote_call(method, param).await.map(Some)
+    }
+
+    /// The methods of the object, which are sent to the IPC server when the object is registered.
+    /// The server may then reject the calls of other methods, or with an invalid parameter,
+    /// before they reach the object.
+    fn methods(&self) -> Option<Vec<MethodDescription>> {
+        None
+    }
 }
 
 /// An object that is responsible in registering the object to the IPC server,
 /// and spawning a tokio task to handling incoming remote method calls from
 /// other processes.
+///
+/// Objects can be registered and unregistered before and after the dispatcher is spawned.
+///
+/// Once the server announces that it is shutting down, new requests fail with
+/// StaticReplies::ServerShuttingDown, while the calls in flight are still answered.
 pub struct ObjectDispatcher {
-    socket: Arc<Mutex<TcpStream>>,
-    list: Arc<Mutex<HashMap<String, Box<dyn SharedObject>>>>,
+    reader: Arc<Mutex<FramedStream<ReadHalf>>>,
+    writer: Arc<Mutex<FramedStream<WriteHalf>>>,
+    list: Arc<Mutex<HashMap<String, Arc<dyn SharedObject>>>>,
+    pending: PendingReplies,
+    shutting_down: Arc<AtomicBool>,
+    next_id: u64,
+    spawned: bool,
+    /// The messages received while waiting for a reply, before the dispatcher is spawned.
+    backlog: Vec<Envelope<IncomingMessage>>,
 }
 
 impl ObjectDispatcher {
     /// Create a new ObjectDispatcher object and connects to the IPC server.
     pub async fn new() -> Result<Self, Error> {
-        let server_address = std::env::var(ENV_SERVER_ADDRESS).unwrap_or(SERVER_ADDRESS.to_owned());
-        let stream = TcpStream::connect(server_address)
+        let address =
+            Address::from_env().map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+        Self::with_transport(&address).await
+    }
+
+    /// Create a new ObjectDispatcher object that connects to the IPC server through the transport.
+    pub async fn with_transport(transport: &dyn Transport) -> Result<Self, Error> {
+        let (reader, writer) = transport
+            .connect()
             .await
             .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
 
         Ok(Self {
-            socket: Arc::new(Mutex::new(stream)),
+            reader: Arc::new(Mutex::new(FramedStream::new(reader))),
+            writer: Arc::new(Mutex::new(FramedStream::new(writer))),
             list: Arc::new(Mutex::new(HashMap::new())),
+            pending: Arc::new(Mutex::new(Some(HashMap::new()))),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            next_id: 1,
+            spawned: false,
+            backlog: Vec::new(),
         })
     }
+
+    /// Returns true once the server has announced that it is shutting down.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// The error of a request that cannot be answered anymore.
+    fn connection_error(&self) -> Error {
+        let reply = if self.is_shutting_down() {
+            StaticReplies::ServerShuttingDown
+        } else {
+            StaticReplies::ServerConnectionError
+        };
+        Error::new(JsonElem::String(reply.to_string()))
+    }
+
+    /// Introduces the process to the server with an optional display name for its logs,
+    /// and returns the id the server gave to this connection.
+    pub async fn hello(&mut self, name: Option<&str>) -> Result<Welcome, Error> {
+        match self
+            .request(OutgoingMessage::Hello(Hello::new(name)))
+            .await?
+        {
+            IncomingMessage::Welcome(welcome) => Ok(welcome),
+            IncomingMessage::Error(msg) => Err(msg),
+            _ => Err(Error::new(JsonElem::String(
+                StaticReplies::InvalidResponseData.to_string(),
+            ))),
+        }
+    }
+
     /// This registers the Shared Object into the IPC server.
+    /// It fails if another process already registered an object with the same name.
     pub async fn register_object(
         &mut self,
         object: &str,
         shared_object: Box<dyn SharedObject>,
     ) -> Result<(), Error> {
-        let mut list = self.list.lock().await;
+        self.register_object_with_policy(object, shared_object, RegisterPolicy::Reject)
+            .await
+    }
 
-        list.insert(object.to_string(), shared_object);
+    /// This registers the Shared Object into the IPC server, and tells the server what to do
+    /// if another process already registered an object with the same name.
+    /// An object on standby is served as soon as the server hands it over.
+    pub async fn register_object_with_policy(
+        &mut self,
+        object: &str,
+        shared_object: Box<dyn SharedObject>,
+        policy: RegisterPolicy,
+    ) -> Result<(), Error> {
+        let methods = shared_object.methods();
+        // The object is listed first, so the calls that follow the registration are served.
+        self.list
+            .lock()
+            .await
+            .insert(object.to_string(), Arc::from(shared_object));
 
-        let mut socket = self.socket.lock().await;
-        let object = RegisterObject::new(object).serialize()?;
+        let reply = self
+            .request(OutgoingMessage::Register(
+                RegisterObject::new(object)
+                    .with_policy(policy)
+                    .with_methods(methods),
+            ))
+            .await?;
+        match reply {
+            IncomingMessage::Register(msg) => {
+                log::trace!("Register Object: {:?}", msg);
+                Ok(())
+            }
+            IncomingMessage::Error(msg) => {
+                self.list.lock().await.remove(object);
+                Err(msg)
+            }
+            msg => {
+                log::trace!("Unhandled Message: {:?}", msg);
+                self.list.lock().await.remove(object);
+                Err(Error::new(JsonElem::String(
+                    StaticReplies::InvalidResponseData.to_string(),
+                )))
+            }
+        }
+    }
 
-        socket
-            .write_all(object.as_slice())
-            .await
-            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+    /// Removes the Shared Object from the IPC server, while the other objects
+    /// of this dispatcher stay registered.
+    pub async fn unregister_object(&mut self, object: &str) -> Result<(), Error> {
+        let reply = self
+            .request(OutgoingMessage::Unregister(UnregisterObject::new(object)))
+            .await?;
+        match reply {
+            IncomingMessage::Register(msg) => {
+                log::trace!("Unregister Object: {:?}", msg);
+                // The calls already forwarded by the server were served until now.
+                self.list.lock().await.remove(object);
+                Ok(())
+            }
+            IncomingMessage::Error(msg) => Err(msg),
+            _ => Err(Error::new(JsonElem::String(
+                StaticReplies::InvalidResponseData.to_string(),
+            ))),
+        }
+    }
 
-        let mut buf = Vec::new();
-        let n = read(&mut socket, &mut buf)
+    /// Sends a request to the server and waits for the reply with the same id.
+    /// Once the dispatcher is spawned, its task hands the reply over.
+    async fn request(&mut self, message: OutgoingMessage) -> Result<IncomingMessage, Error> {
+        if self.is_shutting_down() {
+            return Err(self.connection_error());
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let rx = if self.spawned {
+            let (tx, rx) = oneshot::channel();
+            match self.pending.lock().await.as_mut() {
+                Some(pending) => pending.insert(id, tx),
+                None => return Err(self.connection_error()),
+            };
+            Some(rx)
+        } else {
+            None
+        };
+
+        let data = Envelope::new(message)
+            .with_id(Some(id))
+            .serialize()
+            .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+        self.writer
+            .lock()
+            .await
+            .write_frame(data.as_slice())
             .await
             .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
-        if n == 0 {
-            Err(Error::new(JsonElem::String(
-                StaticReplies::ServerConnectionError.to_string(),
-            )))
-        } else {
-            let msg: IncomingMessage = serde_json::from_slice(&buf[0..n])
-                .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
 
-            match msg {
-                IncomingMessage::Register(msg) => {
-                    log::trace!("Register Object: {:?}", msg);
-                    Ok(())
+        if let Some(rx) = rx {
+            return rx.await.map_err(|_| self.connection_error());
+        }
+        let mut reader = self.reader.lock().await;
+        loop {
+            let buf = reader
+                .read_frame()
+                .await
+                .map_err(|e| Error::new(JsonElem::String(e.to_string())))?
+                .ok_or_else(|| self.connection_error())?;
+            let msg: Envelope<IncomingMessage> = serde_json::from_slice(&buf)
+                .map_err(|e| Error::new(JsonElem::String(e.to_string())))?;
+            // Legacy servers answer without an id.
+            match msg.message {
+                // Calls may arrive as soon as the object is registered, they are served
+                // once the dispatcher is spawned, as the events that are not replies.
+                IncomingMessage::CallRequest(_) | IncomingMessage::Event(_) => {
+                    self.backlog.push(msg)
                 }
-                IncomingMessage::Error(msg) => Err(Error::new(JsonElem::String(msg.to_string()))),
-                _ => {
-                    log::trace!("Unhandled Message: {:?}", msg);
-                    Ok(())
+                // The notice has no id, it is not the reply of a legacy server.
+                IncomingMessage::Shutdown(notice) => {
+                    log::info!("The server is shutting down in {} ms", notice.deadline_ms);
+                    self.shutting_down.store(true, Ordering::Relaxed);
+                    return Err(self.connection_error());
                 }
+                _ if msg.id.is_none() || msg.id == Some(id) => return Ok(msg.message),
+                _ => log::trace!("Unexpected reply: {:?}", msg),
             }
         }
     }
 
     /// This handles remote object method call from other processess.
     /// It spawns a tokio task to handle the calls asynchronously and sends
-    /// back the response back to the remote process.
+    /// back the response back to the remote process. Every call runs in its own
+    /// task, so a slow method does not hold back the other calls.
     pub async fn spawn(&mut self) -> JoinHandle<()> {
-        let socket = self.socket.clone();
+        let reader = self.reader.clone();
+        let writer = self.writer.clone();
         let list = self.list.clone();
+        let pending = self.pending.clone();
+        let shutting_down = self.shutting_down.clone();
+        let backlog = std::mem::take(&mut self.backlog);
+        self.spawned = true;
         tokio::spawn(async move {
+            for msg in backlog {
+                Self::dispatch(msg, &list, &writer, &pending, &shutting_down).await;
+            }
+            let mut reader = reader.lock().await;
             loop {
-                let mut socket = socket.lock().await;
-                let mut buf = Vec::new();
-                let n = read(&mut socket, &mut buf).await.map_or_else(
-                    |e| {
+                let buf = match reader.read_frame().await {
+                    Ok(Some(buf)) => {
+                        log::trace!("Read size: {}", buf.len());
+                        buf
+                    }
+                    Ok(None) => {
+                        log::error!("Error: server connection error");
+                        break;
+                    }
+                    Err(e) => {
                         log::error!("{:?}", e);
-                        0
-                    },
-                    |size: usize| {
-                        log::trace!("Read size: {}", size);
-                        size
-                    },
-                );
-
-                if n == 0 {
-                    log::error!("Error: server connection error");
-                    break;
-                } else if let Ok(msg) = serde_json::from_slice(&buf[0..n]) {
-                    match msg {
-                        IncomingMessage::CallRequest(request) => {
-                            log::trace!("CallObjectRequest: {:?}", &request);
-                            let val = list.lock().await;
-                            let response = if let Some(call) = val.get(&request.object) {
-                                match call.remote_call(&request.method, request.param).await {
-                                    Ok(response) => OutgoingMessage::CallResponse(
-                                        CallObjectResponse::new(response),
-                                    ),
-                                    Err(err) => OutgoingMessage::Error(err),
-                                }
-                            } else {
-                                OutgoingMessage::Error(Error::new(JsonElem::String(
-                                    StaticReplies::ObjectNotFound.to_string(),
-                                )))
-                            };
-                            socket
-                                .write_all(response.serialize().unwrap().as_slice())
-                                .await
-                                .unwrap_or_else(|e| log::error!("{:?}", e));
-                        }
-                        _ => {
-                            log::trace!("Unhandled Message: {:?}", msg);
-                        }
+                        break;
                     }
+                };
+
+                if let Ok(msg) = serde_json::from_slice::<Envelope<IncomingMessage>>(&buf) {
+                    Self::dispatch(msg, &list, &writer, &pending, &shutting_down).await;
                 } else {
                     let response = OutgoingMessage::Error(Error::new(JsonElem::String(
                         StaticReplies::SerdeParseError.to_string(),
                     )));
-                    socket
-                        .write_all(response.serialize().unwrap().as_slice())
+                    writer
+                        .lock()
+                        .await
+                        .write_frame(response.serialize().unwrap().as_slice())
                         .await
                         .unwrap_or_else(|e| log::error!("{:?}", e));
                 }
-                tokio::task::yield_now().await;
             }
+
+            // Dropping the senders wakes up the requests still waiting for a reply.
+            pending.lock().await.take();
         })
     }
+
+    /// Serves a call request, or hands a reply over to the request waiting for it.
+    async fn dispatch(
+        msg: Envelope<IncomingMessage>,
+        list: &Mutex<HashMap<String, Arc<dyn SharedObject>>>,
+        writer: &Arc<Mutex<FramedStream<WriteHalf>>>,
+        pending: &PendingReplies,
+        shutting_down: &AtomicBool,
+    ) {
+        match msg.message {
+            IncomingMessage::CallRequest(request) => {
+                log::trace!("CallObjectRequest: {:?}", &request);
+                let object = list.lock().await.get(&request.object).cloned();
+                let writer = writer.clone();
+                tokio::spawn(async move {
+                    let response = if let Some(call) = object {
+                        match call.remote_call_value(&request.method, request.param).await {
+                            Ok(Some(response)) => {
+                                OutgoingMessage::CallResponse(CallObjectResponse::new(response))
+                            }
+                            Ok(None) => OutgoingMessage::CallResponse(CallObjectResponse::null()),
+                            Err(err) => OutgoingMessage::Error(err),
+                        }
+                    } else {
+                        OutgoingMessage::Error(Error::new(JsonElem::String(
+                            StaticReplies::ObjectNotFound.to_string(),
+                        )))
+                    };
+                    // The reply carries the id of the request it answers.
+                    let response = Envelope::new(response).with_id(msg.id);
+                    writer
+                        .lock()
+                        .await
+                        .write_frame(response.serialize().unwrap().as_slice())
+                        .await
+                        .unwrap_or_else(|e| log::error!("{:?}", e));
+                });
+            }
+            IncomingMessage::Event(event) if event.event == EVENT_OBJECT_UNREGISTERED => {
+                // The object was taken over by another client, its calls are no longer served here.
+                let object = match &event.result {
+                    JsonElem::HashMap(result) => result.get("object"),
+                    _ => None,
+                };
+                if let Some(JsonElem::String(object)) = object {
+                    if list.lock().await.remove(object).is_some() {
+                        log::info!("{} is registered by another client", object);
+                    }
+                }
+            }
+            IncomingMessage::Shutdown(notice) => {
+                // The calls in flight are still answered until the connection closes.
+                log::info!("The server is shutting down in {} ms", notice.deadline_ms);
+                shutting_down.store(true, Ordering::Relaxed);
+            }
+            reply => {
+                let sender = match msg.id {
+                    Some(id) => pending.lock().await.as_mut().and_then(|p| p.remove(&id)),
+                    None => None,
+                };
+                match sender {
+                    Some(sender) => sender.send(reply).unwrap_or_else(|reply| {
+                        log::trace!("Request is no longer waiting: {:?}", reply);
+                    }),
+                    None => log::trace!("Unhandled Message: {:?}", reply),
+                }
+            }
+        }
+    }
 }
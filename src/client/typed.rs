@@ -0,0 +1,313 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use json_elem::jsonelem::JsonElem;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::error::{Error, ErrorKind};
+use super::message::StaticReplies;
+
+/// The arguments of a call, read from the fields of its parameter.
+/// It is used by the SharedObject implementations generated by `#[ipc_object]`.
+#[derive(Debug, Default)]
+pub struct Params {
+    fields: HashMap<String, JsonElem>,
+}
+
+impl Params {
+    /// Reads the parameter of a call, which is an object or nothing.
+    pub fn new(param: Option<JsonElem>) -> Result<Self, Error> {
+        match param {
+            Some(JsonElem::HashMap(fields)) => Ok(Self { fields }),
+            None => Ok(Self::default()),
+            Some(_) => Err(invalid_parameter("the parameter must be an object")),
+        }
+    }
+
+    /// Deserializes the argument, a missing argument is read as null.
+    pub fn take<T: DeserializeOwned>(&mut self, name: &str) -> Result<T, Error> {
+        let result = match self.fields.remove(name) {
+            Some(value) => from_elem(&value).map_err(|e| e.to_string()),
+            None => serde_json::from_value(serde_json::Value::Null)
+                .map_err(|_| "it is required".to_string()),
+        };
+        result.map_err(|e| invalid_parameter(&format!("{}: {}", name, e)))
+    }
+}
+
+/// The arguments of a call, written into the fields of its parameter.
+/// It is used by the proxies generated by `#[ipc_proxy]`.
+#[derive(Debug, Default)]
+pub struct Args {
+    fields: HashMap<String, JsonElem>,
+}
+
+impl Args {
+    /// Creates the arguments of a call that has none yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serializes the argument, an argument that serializes to null is left out.
+    pub fn push<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), Error> {
+        let value = to_elem(value).map_err(|e| {
+            Error::new(JsonElem::String(format!("{}: {}", name, e))).with_kind(e.kind())
+        })?;
+        if let Some(value) = value {
+            self.fields.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Returns the parameter of the call, nothing if it has no arguments.
+    pub fn into_param(self) -> Option<JsonElem> {
+        (!self.fields.is_empty()).then_some(JsonElem::HashMap(self.fields))
+    }
+}
+
+/// Deserializes the result of a call, where None stands for null.
+/// "OK", the result of a method without a value, is read as null only by the types that
+/// cannot take the string, like `()`, so a string "OK" is never lost.
+pub fn from_response<T: DeserializeOwned>(response: Option<JsonElem>) -> Result<T, Error> {
+    let response = match response {
+        Some(response) => response,
+        None => return from_null().map_err(invalid_response),
+    };
+    from_elem(&response).or_else(|e| match is_ok(&response) {
+        true => from_null().map_err(|_| invalid_response(e)),
+        false => Err(invalid_response(e)),
+    })
+}
+
+/// Reads the result of a call to a method without a value, which is "OK" or null.
+pub fn from_unit_response(response: Option<JsonElem>) -> Result<(), Error> {
+    match response {
+        Some(response) if !is_ok(&response) => Err(invalid_response(
+            Error::new(JsonElem::String(format!("unexpected value {}", response)))
+                .with_kind(ErrorKind::Deserialize),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The result of a method without a value.
+pub fn unit_response() -> Option<JsonElem> {
+    Some(JsonElem::String(StaticReplies::Ok.to_string()))
+}
+
+/// Returns the result of a call as JsonElem, which has no null, so null is returned as "OK".
+pub fn null_as_ok(response: Option<JsonElem>) -> JsonElem {
+    response.unwrap_or_else(|| JsonElem::String(StaticReplies::Ok.to_string()))
+}
+
+/// Serializes the result of a call. A value that serializes to null, like `()`, is sent as "OK".
+pub fn to_response<T: Serialize + ?Sized>(value: &T) -> Result<JsonElem, Error> {
+    Ok(null_as_ok(to_elem(value)?))
+}
+
+/// Serializes the value into JsonElem, nothing if it serializes to null.
+/// Fails with ErrorKind::Serialize.
+pub fn to_elem<T: Serialize + ?Sized>(value: &T) -> Result<Option<JsonElem>, Error> {
+    let value = serde_json::to_value(value).map_err(|e| serialize_error(&e.to_string()))?;
+    from_json_value(value)
+}
+
+/// Deserializes the JsonElem into the type. Fails with ErrorKind::Deserialize.
+pub fn from_elem<T: DeserializeOwned>(elem: &JsonElem) -> Result<T, Error> {
+    serde_json::from_value(to_json_value(elem))
+        .map_err(|e| Error::new(JsonElem::String(e.to_string())).with_kind(ErrorKind::Deserialize))
+}
+
+/// Converts the JsonElem into a serde_json Value.
+/// A float that is not finite becomes null, as serde_json writes it.
+pub fn to_json_value(elem: &JsonElem) -> serde_json::Value {
+    match elem {
+        JsonElem::Integer(value) => serde_json::Value::from(*value),
+        JsonElem::Float(value) => serde_json::Value::from(*value),
+        JsonElem::Bool(value) => serde_json::Value::Bool(*value),
+        JsonElem::String(value) => serde_json::Value::String(value.clone()),
+        JsonElem::Vec(values) => values.iter().map(to_json_value).collect(),
+        JsonElem::HashMap(fields) => fields
+            .iter()
+            .map(|(name, value)| (name.clone(), to_json_value(value)))
+            .collect(),
+    }
+}
+
+/// Converts the serde_json Value into a JsonElem, nothing for null.
+/// JsonElem has no null, so the null fields of objects are left out, which serde reads
+/// back as None, and a null in an array fails with ErrorKind::Serialize, as an integer
+/// that does not fit in 32 bits, which would not read back as an integer.
+pub fn from_json_value(value: serde_json::Value) -> Result<Option<JsonElem>, Error> {
+    let elem = match value {
+        serde_json::Value::Null => return Ok(None),
+        serde_json::Value::Bool(value) => JsonElem::Bool(value),
+        serde_json::Value::Number(number) if number.is_f64() => {
+            JsonElem::Float(number.as_f64().unwrap_or(f64::NAN))
+        }
+        serde_json::Value::Number(number) => number
+            .as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .map(JsonElem::Integer)
+            .ok_or_else(|| serialize_error(&format!("{} does not fit in 32 bits", number)))?,
+        serde_json::Value::String(value) => JsonElem::String(value),
+        serde_json::Value::Array(values) => JsonElem::Vec(
+            values
+                .into_iter()
+                .map(|value| {
+                    from_json_value(value)?
+                        .ok_or_else(|| serialize_error("an array cannot hold null"))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        serde_json::Value::Object(fields) => {
+            let mut elems = HashMap::new();
+            for (name, value) in fields {
+                if let Some(value) = from_json_value(value)? {
+                    elems.insert(name, value);
+                }
+            }
+            JsonElem::HashMap(elems)
+        }
+    };
+    Ok(Some(elem))
+}
+
+/// Serializes the error of a call. An Error is sent as it is.
+pub fn to_error<E: Serialize + 'static>(error: E) -> Error {
+    if let Some(error) = (&error as &dyn Any).downcast_ref::<Error>() {
+        return error.clone();
+    }
+    match to_response(&error) {
+        Ok(error) => Error::new(error),
+        Err(e) => e,
+    }
+}
+
+/// Parses a JSON Schema generated by `#[ipc_object]`.
+pub fn schema(schema: &str) -> JsonElem {
+    serde_json::from_str(schema).unwrap_or_else(|_| JsonElem::HashMap(HashMap::new()))
+}
+
+/// The error of a call to a method the object does not have.
+pub fn method_not_found() -> Error {
+    Error::new(JsonElem::String(StaticReplies::MethodNotFound.to_string()))
+}
+
+fn is_ok(response: &JsonElem) -> bool {
+    matches!(response, JsonElem::String(response) if response == StaticReplies::Ok.as_ref())
+}
+
+fn from_null<T: DeserializeOwned>() -> Result<T, Error> {
+    serde_json::from_value(serde_json::Value::Null)
+        .map_err(|e| Error::new(JsonElem::String(e.to_string())).with_kind(ErrorKind::Deserialize))
+}
+
+fn invalid_response(error: Error) -> Error {
+    Error::new(JsonElem::String(format!(
+        "{}: {}",
+        StaticReplies::InvalidResponseData,
+        error
+    )))
+    .with_kind(error.kind())
+}
+
+fn serialize_error(reason: &str) -> Error {
+    Error::new(JsonElem::String(reason.to_string())).with_kind(ErrorKind::Serialize)
+}
+
+fn invalid_parameter(reason: &str) -> Error {
+    Error::new(JsonElem::String(format!(
+        "{}: {}",
+        StaticReplies::InvalidParameter,
+        reason
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use json_elem::jsonelem::JsonElem;
+    use serde_json::json;
+
+    use crate::client::error::ErrorKind;
+    use crate::client::typed::{
+        from_elem, from_json_value, from_response, from_unit_response, to_elem, to_json_value,
+    };
+
+    #[test]
+    fn test_json_value() {
+        let value =
+            json!({"name": "mango", "count": 2, "price": 1.5, "tags": ["ripe"], "note": null});
+        let elem = from_json_value(value).unwrap().unwrap();
+        let JsonElem::HashMap(fields) = &elem else {
+            panic!("Unexpected value {:?}", elem);
+        };
+        assert_eq!(fields["count"], JsonElem::Integer(2));
+        assert_eq!(fields["price"], JsonElem::Float(1.5));
+        assert!(!fields.contains_key("note"));
+        assert_eq!(
+            to_json_value(&elem),
+            json!({"name": "mango", "count": 2, "price": 1.5, "tags": ["ripe"]})
+        );
+
+        assert_eq!(from_json_value(json!(null)).unwrap(), None);
+        for value in [json!(5_000_000_000_i64), json!(u64::MAX)] {
+            assert_eq!(
+                from_json_value(value).unwrap_err().kind(),
+                ErrorKind::Serialize
+            );
+        }
+        assert_eq!(
+            from_json_value(json!(5e9)).unwrap(),
+            Some(JsonElem::Float(5e9))
+        );
+        assert_eq!(
+            from_json_value(json!([1, null])).unwrap_err().kind(),
+            ErrorKind::Serialize
+        );
+
+        assert_eq!(to_elem(&None::<u32>).unwrap(), None);
+        assert_eq!(to_elem(&Some(3)).unwrap(), Some(JsonElem::Integer(3)));
+        assert_eq!(
+            from_elem::<Option<u32>>(&JsonElem::Integer(3)).unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            from_elem::<u32>(&JsonElem::Bool(true)).unwrap_err().kind(),
+            ErrorKind::Deserialize
+        );
+    }
+
+    #[test]
+    fn test_response() {
+        let ok = || Some(JsonElem::String("OK".into()));
+        assert_eq!(
+            from_response::<Option<String>>(ok()).unwrap(),
+            Some("OK".to_string())
+        );
+        assert_eq!(
+            from_response::<serde_json::Value>(ok()).unwrap(),
+            json!("OK")
+        );
+        assert_eq!(from_response::<Option<String>>(None).unwrap(), None);
+        assert_eq!(
+            from_response::<serde_json::Value>(None).unwrap(),
+            json!(null)
+        );
+        // The result of a method without a value.
+        from_response::<()>(ok()).unwrap();
+        from_unit_response(ok()).unwrap();
+        from_unit_response(None).unwrap();
+        assert_eq!(
+            from_unit_response(Some(JsonElem::Integer(1)))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Deserialize
+        );
+        assert_eq!(
+            from_response::<u32>(None).unwrap_err().kind(),
+            ErrorKind::Deserialize
+        );
+    }
+}
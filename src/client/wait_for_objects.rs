@@ -1,79 +1,163 @@
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+use std::fmt;
+use std::time::Duration;
+
+use json_elem::jsonelem::JsonElem;
+use serde_derive::Deserialize;
+
+use crate::transport::{Address, Transport};
+
+use super::connector::Connector;
+use super::error::Error;
+use super::frame::FramedStream;
+use super::message::{
+    Envelope, IncomingMessage, ListObjects, OutgoingMessage, StaticReplies, SERVER_OBJECT,
 };
+use super::typed;
+
+/// The id of the request for the objects.
+const WAIT_ID: u64 = 1;
 
-use crate::{CHUNK_SIZE, ENV_SERVER_ADDRESS, SERVER_ADDRESS};
+/// How long a legacy server is left alone before it is asked again for the objects.
+const LEGACY_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
-use super::message::{IncomingMessage, ListObjects, OutgoingMessage};
+/// How long the server is given to tell which objects are missing once the wait timed out.
+const LIST_OBJECTS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The reason the objects could not be waited for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaitError {
+    /// The objects that were still missing when the timeout expired.
+    Timeout(Vec<String>),
+    /// The IPC server could not be reached, or closed the connection.
+    Connection(Error),
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitError::Timeout(missing) => {
+                write!(f, "timed out waiting for {}", missing.join(", "))
+            }
+            WaitError::Connection(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
 
 /// A function that will guarantees that the object is already available for
 /// remote method calls for synchronization purposes.
+/// It waits forever if the objects never show up, and returns early if the IPC server
+/// cannot be reached, see wait_for_objects_timeout to know what went wrong.
 pub async fn wait_for_objects(list: Vec<String>) {
-    let server_address = std::env::var(ENV_SERVER_ADDRESS).unwrap_or(SERVER_ADDRESS.to_owned());
-    let mut stream = TcpStream::connect(server_address).await.unwrap();
+    match Address::from_env() {
+        Ok(address) => wait_for_objects_with(&address, list).await,
+        Err(e) => log::error!("{:?}", e),
+    }
+}
 
-    loop {
-        stream
-            .write_all(
-                OutgoingMessage::WaitForObjects(ListObjects::new(list.clone()))
-                    .serialize()
-                    .unwrap()
-                    .as_slice(),
-            )
-            .await
-            .unwrap_or_else(|e| {
-                log::trace!("{:?}", e);
-            });
-
-        let mut buf = Vec::new();
-        let n = read(&mut stream, &mut buf).await.map_or_else(
-            |e| {
-                log::error!("{:?}", e);
-                0
-            },
-            |size: usize| {
-                log::trace!("Read size: {}", size);
-                size
-            },
-        );
-        if n == 0 {
-            return;
-        }
+/// Waits for the objects on the IPC server reached through the transport.
+pub async fn wait_for_objects_with(transport: &dyn Transport, list: Vec<String>) {
+    wait(transport, &list).await.unwrap_or_else(|e| {
+        log::error!("{}", e);
+    });
+}
 
-        if let Ok(response) = serde_json::from_slice(&buf[0..n]) {
-            if let IncomingMessage::WaitForObjects(v) = response {
-                if v.list.is_empty() {
-                    tokio::task::yield_now().await;
-                    continue;
-                } else {
-                    break;
-                }
-            }
-        } else {
-            log::trace!("Serde error!");
+/// Waits until the objects are available, or the timeout expires.
+pub async fn wait_for_objects_timeout(
+    list: Vec<String>,
+    timeout: Duration,
+) -> Result<(), WaitError> {
+    let address = Address::from_env()
+        .map_err(|e| WaitError::Connection(Error::new(JsonElem::String(e.to_string()))))?;
+    wait_for_objects_with_timeout(&address, list, timeout).await
+}
+
+/// Waits until the objects are available on the IPC server reached through the transport,
+/// or the timeout expires.
+pub async fn wait_for_objects_with_timeout(
+    transport: &dyn Transport,
+    list: Vec<String>,
+    timeout: Duration,
+) -> Result<(), WaitError> {
+    match tokio::time::timeout(timeout, wait(transport, &list)).await {
+        Ok(result) => result,
+        Err(_) => Err(WaitError::Timeout(missing_objects(transport, list).await)),
+    }
+}
+
+/// A registered object, as listed by `list_objects` of the SERVER_OBJECT.
+#[derive(Deserialize)]
+struct RegisteredObject {
+    object: String,
+}
+
+/// Asks the SERVER_OBJECT which of the objects are not registered.
+/// Every object is missing if the server cannot tell, like a legacy server.
+async fn missing_objects(transport: &dyn Transport, mut list: Vec<String>) -> Vec<String> {
+    let registered = async {
+        let connector = Connector::connect_with(transport).await?;
+        let objects = connector
+            .remote_call_with_timeout(SERVER_OBJECT, "list_objects", None, LIST_OBJECTS_TIMEOUT)
+            .await?;
+        typed::from_elem::<Vec<RegisteredObject>>(&objects)
+    };
+    match registered.await {
+        Ok(registered) => {
+            list.retain(|item| !registered.iter().any(|object| &object.object == item));
         }
-        tokio::task::yield_now().await;
+        Err(e) => log::trace!("Cannot list the registered objects: {}", e),
     }
+    list
 }
 
-async fn read(socket: &mut TcpStream, data: &mut Vec<u8>) -> std::io::Result<usize> {
+/// Asks the server for the objects in a single request, which the server holds until
+/// the last of them is registered.
+/// Legacy servers answer right away with an empty list while some objects are missing,
+/// they are asked again until the list comes back.
+async fn wait(transport: &dyn Transport, list: &[String]) -> Result<(), WaitError> {
+    let connection_error =
+        |e: &dyn fmt::Display| WaitError::Connection(Error::new(JsonElem::String(e.to_string())));
+    let (reader, writer) = transport
+        .connect()
+        .await
+        .map_err(|e| connection_error(&e))?;
+    let mut reader = FramedStream::new(reader);
+    let mut writer = FramedStream::new(writer);
+    let data = Envelope::new(OutgoingMessage::WaitForObjects(ListObjects::new(
+        list.to_vec(),
+    )))
+    .with_id(Some(WAIT_ID))
+    .serialize()
+    .map_err(|e| connection_error(&e))?;
+
     loop {
-        let mut buffer = [0u8; CHUNK_SIZE];
-        match socket.read(&mut buffer).await {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    return Ok(bytes_read);
-                }
-                data.extend_from_slice(&buffer[0..bytes_read]);
-
-                if bytes_read < CHUNK_SIZE {
-                    return Ok(data.len());
-                }
-            }
-            Err(e) => {
-                return Err(e);
+        writer
+            .write_frame(&data)
+            .await
+            .map_err(|e| connection_error(&e))?;
+        loop {
+            let buf = match reader.read_frame().await {
+                Ok(Some(buf)) => buf,
+                Ok(None) => return Err(connection_error(&StaticReplies::ServerConnectionError)),
+                Err(e) => return Err(connection_error(&e)),
+            };
+            match serde_json::from_slice::<Envelope<IncomingMessage>>(&buf) {
+                Ok(Envelope {
+                    id: Some(WAIT_ID) | None,
+                    message: IncomingMessage::WaitForObjects(v),
+                    ..
+                }) if !v.list.is_empty() || list.is_empty() => return Ok(()),
+                Ok(Envelope {
+                    id: None,
+                    message: IncomingMessage::WaitForObjects(_),
+                    ..
+                }) => break,
+                Ok(response) => log::trace!("Unexpected response: {:?}", response),
+                Err(e) => log::trace!("{:?}", e),
             }
         }
+        log::trace!("The objects are not available yet, asking again");
+        tokio::time::sleep(LEGACY_POLL_INTERVAL).await;
     }
 }
@@ -1,6 +1,31 @@
+// The code generated by the macros refers to this crate by its name, from inside of it as well.
+extern crate self as ipc_client;
+
 pub mod client;
+pub mod logger;
+pub mod server;
+#[cfg(test)]
+mod test;
+pub mod transport;
+
+pub use ipc_server_macros::{ipc_object, ipc_proxy};
+
+/// What the code generated by the macros uses, which is not part of the API.
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use json_elem;
+}
 
 pub const SERVER_ADDRESS: &str = "127.0.0.1:1986";
 pub const CHUNK_SIZE: usize = 4096;
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
 pub const ENV_LOGGER: &str = "RUST_LOG";
 pub const ENV_SERVER_ADDRESS: &str = "ENV_SERVER_ADDRESS";
+pub const ENV_SOCKET_MODE: &str = "ENV_SOCKET_MODE";
+/// The default permissions of a Unix domain socket, read and write for the owner and the group.
+pub const SOCKET_MODE: u32 = 0o660;
+pub const ENV_TLS_CERT: &str = "ENV_TLS_CERT";
+pub const ENV_TLS_KEY: &str = "ENV_TLS_KEY";
+pub const ENV_TLS_CA: &str = "ENV_TLS_CA";
+pub const ENV_TLS_SERVER_NAME: &str = "ENV_TLS_SERVER_NAME";
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::ENV_LOGGER;
+
+/// Where the log is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LogDestination {
+    #[default]
+    Stdout,
+    Stderr,
+    /// The log is appended to the file, which is created if needed.
+    File(PathBuf),
+}
+
+impl FromStr for LogDestination {
+    type Err = std::convert::Infallible;
+
+    /// `stdout` and `stderr` select the standard streams, anything else is a file path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "stdout" => LogDestination::Stdout,
+            "stderr" => LogDestination::Stderr,
+            path => LogDestination::File(PathBuf::from(path)),
+        })
+    }
+}
+
+impl fmt::Display for LogDestination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogDestination::Stdout => f.write_str("stdout"),
+            LogDestination::Stderr => f.write_str("stderr"),
+            LogDestination::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Logs to stdout at the level set in ENV_LOGGER, info by default.
+pub fn setup_logger() {
+    let level = std::env::var(ENV_LOGGER)
+        .map(|var| var.parse().unwrap_or(log::LevelFilter::Info))
+        .unwrap_or_else(|_| log::LevelFilter::Info);
+
+    init_logger(level, &LogDestination::Stdout).unwrap_or_else(|e| {
+        eprintln!("{:?}", e);
+    });
+}
+
+/// Logs to the destination at the level.
+pub fn init_logger(level: log::LevelFilter, destination: &LogDestination) -> std::io::Result<()> {
+    let dispatch = fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "[{}][{}][{}:{}]: {}",
+                chrono::Local::now().format("%H:%M:%S%.9f"),
+                record.level(),
+                record.target(),
+                record.line().unwrap_or(0),
+                message
+            ))
+        })
+        .level(level);
+
+    let dispatch = match destination {
+        LogDestination::Stdout => dispatch.chain(std::io::stdout()),
+        LogDestination::Stderr => dispatch.chain(std::io::stderr()),
+        LogDestination::File(path) => dispatch.chain(fern::log_file(path)?),
+    };
+    dispatch
+        .apply()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::AlreadyExists, e))
+}
@@ -1,62 +1,176 @@
-mod error;
-mod manager;
-mod message;
-mod server;
-#[cfg(test)]
-mod test;
-
 use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+use ipc_client::logger::init_logger;
+use ipc_client::server::config::{
+    self, AccessConfig, CallsConfig, Config, LimitsConfig, LogConfig, TimeoutsConfig, TlsConfig,
+    UnixConfig,
+};
 
-use ipc_client::ENV_LOGGER;
-use server::Server;
-use tokio::sync::mpsc::unbounded_channel;
-
-use crate::manager::TaskManager;
-
-pub fn setup_logger() {
-    let level = std::env::var(ENV_LOGGER)
-        .map(|var| match var.to_lowercase().as_str() {
-            "trace" => log::LevelFilter::Trace,
-            "debug" => log::LevelFilter::Debug,
-            "info" => log::LevelFilter::Info,
-            "warn" => log::LevelFilter::Warn,
-            "error" => log::LevelFilter::Error,
-            "off" => log::LevelFilter::Off,
-            _ => log::LevelFilter::Info,
-        })
-        .unwrap_or_else(|_| log::LevelFilter::Info);
-
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{}][{}][{}:{}]: {}",
-                chrono::Local::now().format("%H:%M:%S%.9f"),
-                record.level(),
-                record.target(),
-                record.line().unwrap_or(0),
-                message
-            ))
-        })
-        .level(level)
-        .chain(std::io::stdout())
-        .apply()
-        .unwrap_or_else(|e| {
-            eprintln!("{:?}", e);
-        });
+/// An inter-process communication server.
+///
+/// The settings are read from the environment, then from the config file,
+/// then from the command line, each one overriding the previous ones.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// The TOML config file.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Validates the settings and exits without starting the server.
+    #[arg(long)]
+    check_config: bool,
+    /// An address to listen on, as in `127.0.0.1:1986`, `unix:/run/ipc.sock`
+    /// or `tls:0.0.0.0:1986`. Can be repeated.
+    #[arg(short, long, value_name = "ADDRESS")]
+    listen: Vec<String>,
+    /// One of off, error, warn, info, debug and trace.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// stdout, stderr or the path of a file.
+    #[arg(long, value_name = "DESTINATION")]
+    log: Option<String>,
+    /// The number of clients that can be connected at once.
+    #[arg(long)]
+    max_connections: Option<usize>,
+    /// The size in bytes above which a message is rejected.
+    #[arg(long)]
+    max_frame_size: Option<usize>,
+    /// The time in milliseconds a call waits for its response, unless the caller sets one.
+    #[arg(long, value_name = "MS")]
+    call_timeout: Option<u64>,
+    /// The time in milliseconds the calls in flight have to complete on shutdown.
+    #[arg(long, value_name = "MS")]
+    shutdown_timeout: Option<u64>,
+    /// The certificate chain of the `tls:` addresses, as a PEM file.
+    #[arg(long)]
+    tls_cert: Option<PathBuf>,
+    /// The private key of the certificate, as a PEM file.
+    #[arg(long)]
+    tls_key: Option<PathBuf>,
+    /// The authorities that sign client certificates, which are then required.
+    #[arg(long)]
+    tls_ca: Option<PathBuf>,
+    /// The permissions of the `unix:` socket files, in octal.
+    #[arg(long, value_name = "MODE", value_parser = config::parse_mode)]
+    socket_mode: Option<u32>,
+    /// An IP address or network allowed to connect. Can be repeated.
+    #[arg(long, value_name = "NETWORK")]
+    allow: Vec<String>,
+    /// An IP address or network not allowed to connect. Can be repeated.
+    #[arg(long, value_name = "NETWORK")]
+    deny: Vec<String>,
+    /// Checks the calls against the methods their object describes, unless it is
+    /// `--validate-params=false`.
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    validate_params: Option<bool>,
+}
+
+impl Cli {
+    /// Returns the settings given on the command line.
+    fn overrides(&self) -> Config {
+        let some = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        Config {
+            listen: some(&self.listen),
+            log: LogConfig {
+                level: self.log_level.clone(),
+                destination: self.log.clone(),
+            },
+            limits: LimitsConfig {
+                max_connections: self.max_connections,
+                max_frame_size: self.max_frame_size,
+            },
+            timeouts: TimeoutsConfig {
+                call_ms: self.call_timeout,
+                shutdown_ms: self.shutdown_timeout,
+            },
+            tls: TlsConfig {
+                cert: self.tls_cert.clone(),
+                key: self.tls_key.clone(),
+                ca: self.tls_ca.clone(),
+            },
+            unix: UnixConfig {
+                socket_mode: self.socket_mode,
+            },
+            access: AccessConfig {
+                allow: some(&self.allow),
+                deny: some(&self.deny),
+            },
+            calls: CallsConfig {
+                validate_params: self.validate_params,
+            },
+        }
+    }
+
+    /// Returns the settings of the environment, the config file and the command line.
+    fn config(&self) -> std::io::Result<Config> {
+        let mut config = Config::from_env()?;
+        if let Some(path) = &self.config {
+            config = config.merge(Config::from_file(path)?);
+        }
+        Ok(config.merge(self.overrides()))
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Box<dyn Error>> {
-    setup_logger();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match cli
+        .config()
+        .and_then(|config| config.validate().map(|_| config))
+    {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if cli.check_config {
+        println!("Configuration OK");
+        return ExitCode::SUCCESS;
+    }
+
+    match run(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    init_logger(config.log_level()?, &config.log_destination())?;
 
     let version = env!("CARGO_PKG_VERSION");
     log::info!("Starting ipc-server v.{}", version);
 
-    let (tx, rx) = unbounded_channel();
-
-    TaskManager::spawn(rx).await;
-    Server::spawn(tx).await;
+    let server = config.builder()?.spawn().await?;
+    shutdown_signal().await?;
+    log::info!("Shutting down ipc-server v.{}", version);
+    server.shutdown().await;
 
     log::info!("Stopping ipc-server v.{}", version);
     Ok(())
 }
+
+/// Waits for SIGTERM or SIGINT.
+#[cfg(unix)]
+async fn shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = terminate.recv() => Ok(()),
+        result = tokio::signal::ctrl_c() => result,
+    }
+}
+
+/// Waits for Ctrl-C.
+#[cfg(not(unix))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -1,65 +1,429 @@
+pub mod access;
+pub mod config;
+mod introspection;
+mod legacy;
+pub(crate) mod manager;
+pub(crate) mod message;
+mod schema;
+
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use json_elem::jsonelem::JsonElem;
-use tokio::sync::{Mutex, MutexGuard};
-use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpListener, TcpStream},
-    sync::{
-        mpsc::UnboundedSender,
-        oneshot::{self},
-    },
+use tokio::io::AsyncWrite;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot::{self},
+    watch, OwnedSemaphorePermit, Semaphore,
+};
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::client::error::Error;
+use crate::client::frame::{FramedStream, Framing};
+use crate::client::message::{
+    Envelope, IncomingMessage, ShutdownNotice, StaticReplies, PROTOCOL_VERSION,
 };
+use crate::transport::{Address, Listener, ReadHalf, Transport, WriteHalf};
+use crate::MAX_FRAME_SIZE;
+
+use self::access::AccessRules;
+use self::manager::TaskManager;
+use self::message::{ClientId, IpcMessage, Message, MessageId, Session, SocketHolder};
+
+/// The time the server waits for the calls in flight when it shuts down.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The time the server waits for the response of a call whose caller did not set a timeout.
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a listener waits before accepting again after a failure, like running out of
+/// file descriptors, so it does not spin while the failure lasts.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The lifecycle of the server, as seen by the connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    /// The clients are notified and the calls in flight are answered until the timeout.
+    Draining(Duration),
+    /// Every connection is closed.
+    Stopped,
+}
+
+/// What every listener of the server applies to the clients it accepts.
+#[derive(Debug)]
+struct Limits {
+    connections: Option<Arc<Semaphore>>,
+    max_frame_size: usize,
+    access: AccessRules,
+}
+
+/// A client accepted by a listener.
+struct Client {
+    id: ClientId,
+    /// The peer name given by the transport.
+    name: String,
+    /// Counts the client against the connection limit until it disconnects.
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Configures an IPC server and starts it within the process.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use ipc_client::server::ServerBuilder;
+///
+/// let server = ServerBuilder::new()
+///     .bind("unix:/run/ipc.sock".parse()?)
+///     .spawn()
+///     .await?;
+/// println!("Listening on {}", server.address());
+/// server.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerBuilder {
+    transports: Vec<Box<dyn Transport>>,
+    shutdown_timeout: Duration,
+    call_timeout: Duration,
+    max_connections: Option<usize>,
+    max_frame_size: usize,
+    access: AccessRules,
+    validate_params: bool,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            transports: Vec::new(),
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            call_timeout: CALL_TIMEOUT,
+            max_connections: None,
+            max_frame_size: MAX_FRAME_SIZE,
+            access: AccessRules::default(),
+            validate_params: false,
+        }
+    }
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an address to listen on, it can be called once per address.
+    /// The address from ENV_SERVER_ADDRESS, or SERVER_ADDRESS if it is not set,
+    /// is used if none is added.
+    pub fn bind(self, address: Address) -> Self {
+        self.transport(address)
+    }
+
+    /// Also listens through a transport of its own.
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transports.push(Box::new(transport));
+        self
+    }
+
+    /// Sets the number of clients that can be connected at once, over every listener.
+    /// The clients above the limit are disconnected as soon as they are accepted.
+    /// There is no limit by default.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Sets the size above which a message is rejected and the connection it goes
+    /// through is closed, MAX_FRAME_SIZE by default.
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Sets the rules that select the clients allowed to connect, everyone by default.
+    pub fn access(mut self, access: AccessRules) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Sets the time the server waits for the calls in flight when it shuts down,
+    /// SHUTDOWN_TIMEOUT by default.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Sets the time the server waits for the response of a call before it fails with
+    /// StaticReplies::CallTimeout, CALL_TIMEOUT by default. A caller can set a timeout
+    /// of its own in each CallObjectRequest.
+    pub fn call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    /// Makes the server check the calls to the objects that describe their methods,
+    /// off by default. A call to a method the object does not describe fails with
+    /// StaticReplies::MethodNotFound, and a call whose parameter does not match the
+    /// JSON Schema of the method fails with StaticReplies::InvalidParameter, before
+    /// they are forwarded to the object.
+    pub fn validate_params(mut self, validate: bool) -> Self {
+        self.validate_params = validate;
+        self
+    }
+
+    /// Binds the listeners and serves the clients in the background.
+    pub async fn spawn(self) -> std::io::Result<ServerHandle> {
+        let mut transports = self.transports;
+        if transports.is_empty() {
+            transports.push(Box::new(Address::from_env()?));
+        }
+        let mut listeners = Vec::with_capacity(transports.len());
+        let mut addresses = Vec::with_capacity(transports.len());
+        for transport in transports {
+            let listener = transport.bind().await?;
+            let address = listener.local_addr()?;
+            log::info!("Server listening on {}", address);
+            listeners.push(listener);
+            addresses.push(address);
+        }
+
+        let limits = Arc::new(Limits {
+            connections: self
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            max_frame_size: self.max_frame_size,
+            access: self.access,
+        });
+        let shutdown_timeout = self.shutdown_timeout;
+        let call_timeout = self.call_timeout;
+        let validate_params = self.validate_params;
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(async move {
+            let (tx, rx) = unbounded_channel();
+            let (state_tx, state_rx) = watch::channel(State::Running);
+            let manager = TaskManager::spawn(rx, call_timeout, validate_params).await;
+            let mut serving = JoinSet::new();
+            // The client ids are unique across the listeners.
+            let client_ids = Arc::new(AtomicU64::new(1));
+            for listener in listeners {
+                serving.spawn(Server::serve(
+                    listener,
+                    tx.clone(),
+                    shutdown_rx.clone(),
+                    state_rx.clone(),
+                    limits.clone(),
+                    client_ids.clone(),
+                ));
+            }
+            while serving.join_next().await.is_some() {}
+            Server::drain(&tx, &state_tx, shutdown_timeout).await;
+
+            let _ = state_tx.send(State::Stopped);
+            drop(tx);
+            // The TaskManager stops once every client is gone.
+            let _ = manager.await;
+        });
+
+        Ok(ServerHandle {
+            addresses,
+            shutdown: shutdown_tx,
+            task,
+        })
+    }
+}
+
+/// A running IPC server. Dropping the handle shuts the server down.
+#[must_use = "the server shuts down when its handle is dropped"]
+#[derive(Debug)]
+pub struct ServerHandle {
+    addresses: Vec<Address>,
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// Returns the first address the server is bound to, with the actual port
+    /// if port 0 was requested.
+    pub fn address(&self) -> &Address {
+        &self.addresses[0]
+    }
 
-use ipc_client::{CHUNK_SIZE, ENV_SERVER_ADDRESS, SERVER_ADDRESS};
+    /// Returns every address the server is bound to, in the order they were added.
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    /// Stops accepting clients and sends a shutdown notice to the connected ones.
+    /// The calls in flight are answered until the shutdown timeout, then every
+    /// connection is closed. Waits until the server is stopped.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        self.wait().await;
+    }
 
-use crate::error::Error;
-use crate::message::{IpcMessage, Message, Session};
+    /// Waits until the server is stopped.
+    pub async fn wait(self) {
+        let ServerHandle { shutdown, task, .. } = self;
+        if let Err(e) = task.await {
+            log::error!("{:?}", e);
+        }
+        drop(shutdown);
+    }
+}
 
 /// This is the IPC server, it handles incoming messages from different processes
 /// and send each task to the TaskManager for proper handling.
-pub struct Server;
+pub(crate) struct Server;
 
 impl Server {
-    /// Spawn the IPC server to listen concurrent incoming messages.
-    pub async fn spawn(tx: UnboundedSender<Message>) {
-        let server_address = std::env::var(ENV_SERVER_ADDRESS).unwrap_or(SERVER_ADDRESS.to_owned());
-        let listener = TcpListener::bind(server_address.clone()).await.unwrap();
-
-        log::trace!("Server listening on {}", server_address);
+    /// Accepts the clients of the listener and handles each one in its own task,
+    /// until the server is shut down.
+    /// A connected client costs nothing while it is idle, its task only wakes up
+    /// when the socket becomes readable.
+    async fn serve(
+        mut listener: Box<dyn Listener>,
+        tx: UnboundedSender<Message>,
+        mut shutdown: watch::Receiver<bool>,
+        state: watch::Receiver<State>,
+        limits: Arc<Limits>,
+        client_ids: Arc<AtomicU64>,
+    ) {
         loop {
-            let (socket, _) = listener.accept().await.unwrap();
-            tokio::spawn(Server::handle_client(socket, tx.clone()));
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((reader, writer, name)) => {
+                        if !limits.access.allows(&name) {
+                            log::warn!("[{}]: Client rejected by the access rules", name);
+                            continue;
+                        }
+                        let permit = match &limits.connections {
+                            Some(connections) => match connections.clone().try_acquire_owned() {
+                                Ok(permit) => Some(permit),
+                                Err(_) => {
+                                    log::warn!("[{}]: Client rejected, too many connections", name);
+                                    continue;
+                                }
+                            },
+                            None => None,
+                        };
+                        let client = Client {
+                            id: client_ids.fetch_add(1, Ordering::Relaxed),
+                            name,
+                            _permit: permit,
+                        };
+                        tokio::spawn(Server::handle_client(
+                            reader,
+                            writer,
+                            client,
+                            tx.clone(),
+                            state.clone(),
+                            limits.max_frame_size,
+                        ));
+                    }
+                    Err(e) => {
+                        log::error!("{:?}", e);
+                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    }
+                },
+                _ = shutdown.changed() => break,
+            }
+        }
+        log::info!("Server stopped listening on {:?}", listener.local_addr());
+    }
+
+    /// Notifies the clients that the server is shutting down and waits until
+    /// the calls in flight are answered, or the timeout expires.
+    async fn drain(tx: &UnboundedSender<Message>, state: &watch::Sender<State>, timeout: Duration) {
+        let _ = state.send(State::Draining(timeout));
+
+        let (drained_tx, drained_rx) = oneshot::channel();
+        tx.send(Message::Drain(drained_tx)).unwrap_or_else(|e| {
+            log::error!("{:?}", e);
+        });
+        match tokio::time::timeout(timeout, drained_rx).await {
+            Ok(_) => log::info!("Every call in flight is answered"),
+            Err(_) => log::error!("Closing the connections with calls still in flight"),
         }
     }
 
     /// Handles the received messages and pass it into TaskManager for proper handling.
-    async fn handle_client(socket: TcpStream, tx: UnboundedSender<Message>) {
-        let ip = socket.peer_addr().unwrap().to_string();
-        log::info!("[{}]: Client connected", ip);
+    async fn handle_client(
+        reader: ReadHalf,
+        writer: WriteHalf,
+        client: Client,
+        tx: UnboundedSender<Message>,
+        mut state: watch::Receiver<State>,
+        max_frame_size: usize,
+    ) {
+        log::info!("[#{} {}]: Client connected", client.id, client.name);
 
-        let tcp = Arc::new(Mutex::new(socket));
+        // Clients that predate framing are still served, the framing is detected
+        // from the first message they send.
+        let mut reader = FramedStream::detect(reader);
+        reader.set_max_frame_size(max_frame_size);
+        // A client that has not sent anything yet has nothing in flight, it is only
+        // told that the server is shutting down.
+        let mut frame = tokio::select! {
+            frame = reader.read_frame() => frame,
+            _ = state.changed() => {
+                let current = *state.borrow_and_update();
+                if let State::Draining(timeout) = current {
+                    // Its framing is unknown, it is assumed to be a current client.
+                    let mut writer = FramedStream::with_framing(writer, Framing::LengthPrefixed);
+                    writer.set_max_frame_size(max_frame_size);
+                    let notice = ShutdownNotice::new(timeout.as_millis() as u64);
+                    Self::write(&mut writer, Envelope::new(IncomingMessage::Shutdown(notice)))
+                        .await
+                        .unwrap_or_else(|e| log::error!("Error writing data to client: {}", e));
+                }
+                log::info!("[#{} {}]: Client disconnected", client.id, client.name);
+                return;
+            }
+        };
+
+        // Everything written to this client goes through the queue of its writer task,
+        // so replies, events and forwarded calls never wait for the reader.
+        let framing = reader.framing().unwrap_or(Framing::LengthPrefixed);
+        let (writer_tx, writer_rx) = unbounded_channel();
+        let mut writer = FramedStream::with_framing(writer, framing);
+        writer.set_max_frame_size(max_frame_size);
+        tokio::spawn(Self::write_loop(writer, writer_rx));
+        let socket_holder = SocketHolder {
+            id: client.id,
+            name: client.name.clone(),
+            tx: writer_tx,
+        };
+        tx.send(Message::AddClient(socket_holder.clone()))
+            .unwrap_or_else(|e| {
+                log::error!("{:?}", e);
+            });
 
         loop {
-            let mut buffer = Vec::new();
-            // Use try_read to check if there is data available to read without blocking.
-            let mut socket = tcp.lock().await;
-
-            match Self::read(&socket, &mut buffer).await {
-                Ok(bytes_read) => {
-                    if bytes_read == 0 {
-                        // The client has closed the connection.
-                        break;
-                    }
-                    log::trace!(
-                        "SERVER RECEIVED: {}",
-                        String::from_utf8(buffer[0..bytes_read].to_vec()).unwrap()
-                    );
-                    match serde_json::from_slice(&buffer[0..bytes_read]) {
-                        Ok(ipc_message) => {
-                            log::trace!("IpcMessage => {:?}", &ipc_message);
+            match frame {
+                Ok(None) => {
+                    // The client has closed the connection.
+                    break;
+                }
+                Ok(Some(buffer)) => {
+                    let buffer = match framing {
+                        Framing::Legacy => legacy::upgrade(buffer),
+                        Framing::LengthPrefixed => buffer,
+                    };
+                    log::trace!("SERVER RECEIVED: {}", String::from_utf8_lossy(&buffer));
+                    match serde_json::from_slice::<Envelope<IpcMessage>>(&buffer) {
+                        Ok(envelope) if envelope.version != PROTOCOL_VERSION => {
+                            log::error!("Unsupported protocol version: {}", envelope.version);
+                            socket_holder.send(
+                                Envelope::new(IncomingMessage::Error(Error::new(
+                                    JsonElem::String(StaticReplies::UnsupportedVersion.to_string()),
+                                )))
+                                .with_id(envelope.id),
+                            );
+                        }
+                        Ok(envelope) => {
+                            log::trace!("IpcMessage => {:?}", &envelope);
                             let session: Session =
-                                Session::new(ipc_message, ip.clone(), tcp.clone());
+                                Session::new(envelope.id, envelope.message, socket_holder.clone());
 
                             let (oneshot_tx, oneshot_rx) = oneshot::channel();
                             tx.send(Message::ProcessInput(session, oneshot_tx))
@@ -67,80 +431,104 @@ impl Server {
                                     log::error!("{:?}", e);
                                 });
 
-                            let reply = oneshot_rx.await.unwrap_or_else(|e| {
-                                log::error!("{:?}", e);
-                                Vec::new()
+                            // Wait for the reply in the background, so the next requests of
+                            // this client are read while this one is still in progress.
+                            let socket_holder = socket_holder.clone();
+                            tokio::spawn(async move {
+                                // Some messages like event unsubscriptions have no reply.
+                                if let Ok(reply) = oneshot_rx.await {
+                                    socket_holder.send(Envelope::new(reply).with_id(envelope.id));
+                                }
                             });
-                            if let Err(e) = socket.write_all(reply.as_slice()).await {
-                                log::error!("Error writing data to client: {}", e);
-                                break;
-                            }
                         }
                         Err(e) => {
-                            log::error!(
-                                "{}: {}",
-                                e,
-                                String::from_utf8(buffer[0..bytes_read].to_vec()).unwrap()
+                            log::error!("{}: {}", e, String::from_utf8_lossy(&buffer));
+                            let id = serde_json::from_slice::<MessageId>(&buffer)
+                                .ok()
+                                .and_then(|message| message.id);
+                            socket_holder.send(
+                                Envelope::new(IncomingMessage::Error(Error::new(
+                                    JsonElem::String(e.to_string()),
+                                )))
+                                .with_id(id),
                             );
-                            if let Err(e) = socket
-                                .write_all(
-                                    &Error::new(JsonElem::String(e.to_string()))
-                                        .serialize()
-                                        .unwrap(),
-                                )
-                                .await
-                            {
-                                log::error!("Error writing data to client: {}", e);
-                                break;
-                            }
                         }
                     }
                 }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    tokio::task::yield_now().await;
-                }
                 Err(e) => {
                     log::error!("{:?}", e);
                     break;
                 }
             }
+            frame = match Self::next_frame(&mut reader, &mut state, framing, &socket_holder).await {
+                Some(frame) => frame,
+                None => break,
+            };
         }
 
-        let session: Session = Session::new(IpcMessage::None, ip.clone(), tcp.clone());
+        log::info!("[{}]: Client disconnected", socket_holder);
+        let session: Session = Session::new(None, IpcMessage::None, socket_holder);
 
         tx.send(Message::RemoveRegistered(session))
             .unwrap_or_else(|e| {
                 log::error!("{:?}", e);
             });
-        log::info!("[{}]: Client disconnected", ip);
     }
 
-    async fn read(
-        socket: &MutexGuard<'_, TcpStream>,
-        data: &mut Vec<u8>,
-    ) -> std::io::Result<usize> {
+    /// Reads the next message of the client, or returns None once the server is stopped.
+    /// The client is notified as soon as the server starts draining, and keeps being
+    /// served so the responses to the calls in flight get through.
+    async fn next_frame(
+        reader: &mut FramedStream<ReadHalf>,
+        state: &mut watch::Receiver<State>,
+        framing: Framing,
+        socket_holder: &SocketHolder,
+    ) -> Option<std::io::Result<Option<Vec<u8>>>> {
         loop {
-            let mut buffer = [0u8; CHUNK_SIZE];
-
-            match socket.try_read(&mut buffer) {
-                Ok(bytes_read) => {
-                    if bytes_read == 0 {
-                        // The client has closed the connection.
-                        return Ok(bytes_read);
-                    }
-                    data.extend_from_slice(&buffer[0..bytes_read]);
-                    if bytes_read < CHUNK_SIZE {
-                        return Ok(data.len());
+            tokio::select! {
+                frame = reader.read_frame() => return Some(frame),
+                changed = state.changed() => {
+                    let current = *state.borrow_and_update();
+                    match (changed, current) {
+                        (Ok(()), State::Running) => {}
+                        (Ok(()), State::Draining(timeout)) => {
+                            // Legacy clients would not understand the notice.
+                            if framing != Framing::Legacy {
+                                let deadline = timeout.as_millis() as u64;
+                                socket_holder.send(Envelope::new(IncomingMessage::Shutdown(
+                                    ShutdownNotice::new(deadline),
+                                )));
+                            }
+                        }
+                        _ => return None,
                     }
                 }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    return Err(e);
-                }
-                Err(e) => {
-                    log::error!("{:?}", e);
-                    return Err(e);
-                }
             }
         }
     }
+
+    /// Writes the queued messages to the client until every handle to the queue is dropped.
+    async fn write_loop(
+        mut socket: FramedStream<WriteHalf>,
+        mut rx: UnboundedReceiver<Envelope<IncomingMessage>>,
+    ) {
+        while let Some(message) = rx.recv().await {
+            if let Err(e) = Self::write(&mut socket, message).await {
+                log::error!("Error writing data to client: {}", e);
+                break;
+            }
+        }
+    }
+
+    /// Writes the message to the client. Legacy clients only receive the payload.
+    async fn write<S: AsyncWrite + Unpin>(
+        socket: &mut FramedStream<S>,
+        message: Envelope<IncomingMessage>,
+    ) -> std::io::Result<()> {
+        let mut data = message.serialize()?;
+        if socket.framing() == Some(Framing::Legacy) {
+            data = legacy::downgrade(data);
+        }
+        socket.write_frame(&data).await
+    }
 }
@@ -0,0 +1,105 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// An IP address or a network in CIDR notation, as in `10.0.0.0/8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpRule {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpRule {
+    /// Returns true if the address is part of the network.
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid access rule {}", s),
+            )
+        };
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { address, prefix })
+    }
+}
+
+/// The clients allowed to connect, by IP address. A client is rejected if it matches
+/// a deny rule, or if there are allow rules and it matches none of them.
+/// Clients without an IP address, like the ones of a Unix domain socket, are always allowed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessRules {
+    pub allow: Vec<IpRule>,
+    pub deny: Vec<IpRule>,
+}
+
+impl AccessRules {
+    /// Returns true if the client with this peer name may connect.
+    pub fn allows(&self, peer: &str) -> bool {
+        let address = match peer.parse::<SocketAddr>() {
+            Ok(address) => address.ip(),
+            Err(_) => return true,
+        };
+        if self.deny.iter().any(|rule| rule.contains(&address)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|rule| rule.contains(&address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::access::{AccessRules, IpRule};
+
+    #[test]
+    fn test_access_rules() {
+        let rules = AccessRules {
+            allow: vec!["127.0.0.1".parse().unwrap(), "10.0.0.0/8".parse().unwrap()],
+            deny: vec!["10.1.0.0/16".parse().unwrap()],
+        };
+        assert!(rules.allows("127.0.0.1:4000"));
+        assert!(rules.allows("10.2.3.4:4000"));
+        assert!(!rules.allows("10.1.3.4:4000"));
+        assert!(!rules.allows("192.168.1.1:4000"));
+        assert!(!rules.allows("[::1]:4000"));
+        assert!(rules.allows("[::ffff:127.0.0.1]:4000"));
+        assert!(rules.allows("unix:/run/ipc.sock#1"));
+        assert!(AccessRules::default().allows("192.168.1.1:4000"));
+
+        assert!("0.0.0.0/0"
+            .parse::<IpRule>()
+            .unwrap()
+            .contains(&"1.2.3.4".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpRule>().is_err());
+        assert!("localhost".parse::<IpRule>().is_err());
+    }
+}
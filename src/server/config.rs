@@ -0,0 +1,420 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_derive::Deserialize;
+
+use crate::logger::LogDestination;
+use crate::server::access::AccessRules;
+use crate::server::ServerBuilder;
+use crate::transport::tls::{TlsOptions, TlsTransport};
+#[cfg(unix)]
+use crate::transport::unix::UnixTransport;
+use crate::transport::Address;
+use crate::{
+    ENV_LOGGER, ENV_SERVER_ADDRESS, ENV_SOCKET_MODE, ENV_TLS_CA, ENV_TLS_CERT, ENV_TLS_KEY,
+};
+
+/// A frame whose length prefix starts with `{` would be taken for a legacy client,
+/// so frames stay below 0x7B000000 bytes.
+const FRAME_SIZE_LIMIT: usize = 0x7B00_0000;
+
+/// The configuration of the ipc_server binary, as read from a TOML file.
+/// Every setting is optional, the ones that are not set keep the defaults of ServerBuilder.
+///
+/// ```toml
+/// listen = ["unix:/run/ipc.sock", "127.0.0.1:1986"]
+///
+/// [log]
+/// level = "info"
+/// destination = "/var/log/ipc-server.log"
+///
+/// [limits]
+/// max_connections = 1024
+/// max_frame_size = 16777216
+///
+/// [timeouts]
+/// call_ms = 30000
+/// shutdown_ms = 5000
+///
+/// [access]
+/// allow = ["127.0.0.1", "10.0.0.0/8"]
+/// deny = ["10.1.0.0/16"]
+///
+/// [calls]
+/// validate_params = true
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The addresses to listen on, the scheme of each one selects its transport.
+    pub listen: Option<Vec<String>>,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    /// The certificates of the `tls:` addresses.
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// The socket files of the `unix:` addresses.
+    #[serde(default)]
+    pub unix: UnixConfig,
+    #[serde(default)]
+    pub access: AccessConfig,
+    #[serde(default)]
+    pub calls: CallsConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// One of off, error, warn, info, debug and trace.
+    pub level: Option<String>,
+    /// stdout, stderr or the path of a file.
+    pub destination: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_connections: Option<usize>,
+    pub max_frame_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    /// The time a call waits for its response, unless the caller sets a timeout of its own.
+    pub call_ms: Option<u64>,
+    /// The time the calls in flight have to complete when the server shuts down.
+    pub shutdown_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Makes the server require client certificates signed by these authorities.
+    pub ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnixConfig {
+    /// The permissions of the socket files, as in `socket_mode = 0o660`.
+    pub socket_mode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessConfig {
+    /// IP addresses or networks in CIDR notation.
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CallsConfig {
+    /// Checks the calls against the methods their object describes.
+    pub validate_params: Option<bool>,
+}
+
+impl Config {
+    /// Reads the settings that can be set through the environment: ENV_SERVER_ADDRESS,
+    /// ENV_LOGGER, ENV_SOCKET_MODE, ENV_TLS_CERT, ENV_TLS_KEY and ENV_TLS_CA.
+    pub fn from_env() -> std::io::Result<Self> {
+        let socket_mode = match std::env::var(ENV_SOCKET_MODE) {
+            Ok(mode) => Some(parse_mode(&mode)?),
+            Err(_) => None,
+        };
+        Ok(Self {
+            listen: std::env::var(ENV_SERVER_ADDRESS)
+                .ok()
+                .map(|address| vec![address]),
+            log: LogConfig {
+                // ENV_LOGGER may hold filters meant for other loggers, they are ignored.
+                level: std::env::var(ENV_LOGGER)
+                    .ok()
+                    .filter(|level| level.parse::<log::LevelFilter>().is_ok()),
+                destination: None,
+            },
+            tls: TlsConfig {
+                cert: std::env::var_os(ENV_TLS_CERT).map(PathBuf::from),
+                key: std::env::var_os(ENV_TLS_KEY).map(PathBuf::from),
+                ca: std::env::var_os(ENV_TLS_CA).map(PathBuf::from),
+            },
+            unix: UnixConfig { socket_mode },
+            ..Default::default()
+        })
+    }
+
+    /// Reads a TOML config file.
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&content).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e.message()),
+            )
+        })
+    }
+
+    /// Returns this config with every setting of the overrides that is set.
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            listen: overrides.listen.or(self.listen),
+            log: LogConfig {
+                level: overrides.log.level.or(self.log.level),
+                destination: overrides.log.destination.or(self.log.destination),
+            },
+            limits: LimitsConfig {
+                max_connections: overrides
+                    .limits
+                    .max_connections
+                    .or(self.limits.max_connections),
+                max_frame_size: overrides
+                    .limits
+                    .max_frame_size
+                    .or(self.limits.max_frame_size),
+            },
+            timeouts: TimeoutsConfig {
+                call_ms: overrides.timeouts.call_ms.or(self.timeouts.call_ms),
+                shutdown_ms: overrides.timeouts.shutdown_ms.or(self.timeouts.shutdown_ms),
+            },
+            tls: TlsConfig {
+                cert: overrides.tls.cert.or(self.tls.cert),
+                key: overrides.tls.key.or(self.tls.key),
+                ca: overrides.tls.ca.or(self.tls.ca),
+            },
+            unix: UnixConfig {
+                socket_mode: overrides.unix.socket_mode.or(self.unix.socket_mode),
+            },
+            access: AccessConfig {
+                allow: overrides.access.allow.or(self.access.allow),
+                deny: overrides.access.deny.or(self.access.deny),
+            },
+            calls: CallsConfig {
+                validate_params: overrides
+                    .calls
+                    .validate_params
+                    .or(self.calls.validate_params),
+            },
+        }
+    }
+
+    /// Checks every setting without binding anything. The files of the TLS certificates
+    /// are loaded, and the log file is opened, which creates it if needed.
+    pub fn validate(&self) -> std::io::Result<()> {
+        self.log_level()?;
+        if let LogDestination::File(path) = self.log_destination() {
+            std::fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&path)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        }
+        for address in self.addresses()? {
+            if let Address::Tls(address) = address {
+                self.tls_transport(&address)?.check_server()?;
+            }
+        }
+        self.builder().map(|_| ())
+    }
+
+    /// Returns the log level, info if it is not set.
+    pub fn log_level(&self) -> std::io::Result<log::LevelFilter> {
+        match &self.log.level {
+            Some(level) => level
+                .parse()
+                .map_err(|_| invalid(format!("invalid log level {}", level))),
+            None => Ok(log::LevelFilter::Info),
+        }
+    }
+
+    /// Returns where the log is written, stdout if it is not set.
+    pub fn log_destination(&self) -> LogDestination {
+        match &self.log.destination {
+            Some(destination) => {
+                let Ok(destination) = destination.parse();
+                destination
+            }
+            None => LogDestination::Stdout,
+        }
+    }
+
+    /// Returns the addresses to listen on, SERVER_ADDRESS if none is set.
+    pub fn addresses(&self) -> std::io::Result<Vec<Address>> {
+        match &self.listen {
+            Some(listen) if listen.is_empty() => Err(invalid("no address to listen on")),
+            Some(listen) => listen.iter().map(|address| address.parse()).collect(),
+            None => Ok(vec![crate::SERVER_ADDRESS.parse()?]),
+        }
+    }
+
+    /// Returns a ServerBuilder with every setting of the config.
+    pub fn builder(&self) -> std::io::Result<ServerBuilder> {
+        let mut builder = ServerBuilder::new();
+        for address in self.addresses()? {
+            builder = match address {
+                Address::Tls(address) => builder.transport(self.tls_transport(&address)?),
+                #[cfg(unix)]
+                Address::Unix(path) => match self.unix.socket_mode {
+                    Some(mode) => builder.transport(UnixTransport::new(&path, mode)),
+                    None => builder.bind(Address::Unix(path)),
+                },
+                address => builder.bind(address),
+            };
+        }
+
+        if let Some(mode) = self.unix.socket_mode {
+            if mode > 0o7777 {
+                return Err(invalid(format!("invalid socket mode {:o}", mode)));
+            }
+        }
+        if let Some(max_connections) = self.limits.max_connections {
+            if max_connections == 0 {
+                return Err(invalid("max_connections must be at least 1"));
+            }
+            builder = builder.max_connections(max_connections);
+        }
+        if let Some(max_frame_size) = self.limits.max_frame_size {
+            if max_frame_size == 0 || max_frame_size >= FRAME_SIZE_LIMIT {
+                return Err(invalid(format!(
+                    "max_frame_size must be between 1 and {}",
+                    FRAME_SIZE_LIMIT - 1
+                )));
+            }
+            builder = builder.max_frame_size(max_frame_size);
+        }
+        if let Some(call_ms) = self.timeouts.call_ms {
+            if call_ms == 0 {
+                return Err(invalid("call_ms must be at least 1"));
+            }
+            builder = builder.call_timeout(Duration::from_millis(call_ms));
+        }
+        if let Some(shutdown_ms) = self.timeouts.shutdown_ms {
+            builder = builder.shutdown_timeout(Duration::from_millis(shutdown_ms));
+        }
+        if let Some(validate) = self.calls.validate_params {
+            builder = builder.validate_params(validate);
+        }
+
+        let rules = |rules: &Option<Vec<String>>| -> std::io::Result<Vec<_>> {
+            rules.iter().flatten().map(|rule| rule.parse()).collect()
+        };
+        Ok(builder.access(AccessRules {
+            allow: rules(&self.access.allow)?,
+            deny: rules(&self.access.deny)?,
+        }))
+    }
+
+    /// Returns the transport of a `tls:` address, with the certificates of the config.
+    fn tls_transport(&self, address: &str) -> std::io::Result<TlsTransport> {
+        let (cert, key) = match (&self.tls.cert, &self.tls.key) {
+            (Some(cert), Some(key)) => (cert.clone(), key.clone()),
+            _ => {
+                return Err(invalid(format!(
+                    "tls:{} needs a certificate and its key",
+                    address
+                )))
+            }
+        };
+        let options = TlsOptions {
+            cert: Some(cert),
+            key: Some(key),
+            ca: self.tls.ca.clone(),
+            server_name: None,
+        };
+        Ok(TlsTransport::new(address, options))
+    }
+}
+
+/// Parses permissions in octal, as in `660` or `0o660`.
+pub fn parse_mode(mode: &str) -> std::io::Result<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|e| invalid(format!("invalid socket mode {}: {}", mode, e)))
+}
+
+fn invalid(message: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidInput, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::config::Config;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            listen = ["memory:config", "127.0.0.1:0"]
+
+            [log]
+            level = "debug"
+            destination = "stderr"
+
+            [limits]
+            max_connections = 10
+
+            [unix]
+            socket_mode = 0o600
+
+            [access]
+            allow = ["127.0.0.1"]
+
+            [calls]
+            validate_params = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.listen.as_ref().unwrap().len(), 2);
+        assert_eq!(config.log_level().unwrap(), log::LevelFilter::Debug);
+        assert_eq!(config.limits.max_connections, Some(10));
+        assert_eq!(config.unix.socket_mode, Some(0o600));
+        assert_eq!(config.calls.validate_params, Some(true));
+        assert!(config.validate().is_ok());
+
+        assert!(toml::from_str::<Config>("listn = []").is_err());
+        assert!(toml::from_str::<Config>("[limits]\nmax_conections = 1").is_err());
+    }
+
+    #[test]
+    fn test_merge_config() {
+        let env: Config = toml::from_str("listen = [\"a:1\"]\n[log]\nlevel = \"warn\"").unwrap();
+        let file: Config =
+            toml::from_str("listen = [\"b:1\"]\n[limits]\nmax_connections = 5").unwrap();
+        let cli: Config = toml::from_str("listen = [\"c:1\"]").unwrap();
+
+        let config = env.merge(file).merge(cli);
+        assert_eq!(config.listen, Some(vec!["c:1".to_string()]));
+        assert_eq!(config.log.level.as_deref(), Some("warn"));
+        assert_eq!(config.limits.max_connections, Some(5));
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let invalid = [
+            "listen = []",
+            "listen = [\"unix:\"]",
+            "listen = [\"tls:localhost:1986\"]",
+            "[log]\nlevel = \"loud\"",
+            "[limits]\nmax_connections = 0",
+            "[timeouts]\ncall_ms = 0",
+            "[limits]\nmax_frame_size = 2147483647",
+            "[unix]\nsocket_mode = 0o17777",
+            "[access]\ndeny = [\"10.0.0.0/40\"]",
+            "listen = [\"tls:localhost:1986\"]\n[tls]\ncert = \"/nonexistent/cert.pem\"\nkey = \"/nonexistent/key.pem\"",
+            "[log]\ndestination = \"/nonexistent/ipc-server.log\"",
+        ];
+        for config in invalid {
+            let config: Config = toml::from_str(config).unwrap();
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+        assert!(Config::default().validate().is_ok());
+    }
+}
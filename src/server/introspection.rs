@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap};
+
+use json_elem::jsonelem::JsonElem;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::client::error::Error;
+use crate::client::message::{MethodDescription, StaticReplies};
+use crate::client::typed::to_response;
+use crate::server::message::{ClientId, ClientInfo, SocketHolder};
+
+/// The state of the TaskManager that the SERVER_OBJECT tells about.
+pub struct Registry<'a> {
+    pub objects: &'a HashMap<String, SocketHolder>,
+    pub standby: &'a HashMap<String, Vec<SocketHolder>>,
+    pub subscribers: &'a HashMap<String, Vec<SocketHolder>>,
+    pub clients: &'a HashMap<ClientId, ClientInfo>,
+    pub methods: &'a HashMap<(String, ClientId), Vec<MethodDescription>>,
+}
+
+/// A registered object, as listed by `list_objects`.
+#[derive(Serialize)]
+struct ObjectSummary<'a> {
+    object: &'a str,
+    owner: ClientId,
+}
+
+/// A registered object, as described by `describe_object`.
+#[derive(Serialize)]
+struct ObjectDescription<'a> {
+    object: &'a str,
+    owner: ClientInfo,
+    /// The clients queued to take the object over, in order.
+    standby: Vec<ClientInfo>,
+    /// The methods described by the owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    methods: Option<&'a Vec<MethodDescription>>,
+}
+
+/// A connected client, as listed by `list_clients`.
+#[derive(Serialize)]
+struct ClientDescription<'a> {
+    #[serde(flatten)]
+    info: &'a ClientInfo,
+    objects: Vec<&'a str>,
+    subscriptions: Vec<&'a str>,
+}
+
+/// The parameter of `describe_object`.
+#[derive(Deserialize)]
+struct DescribeObject {
+    object: String,
+}
+
+impl Registry<'_> {
+    /// Answers a call to the SERVER_OBJECT.
+    pub fn call(&self, method: &str, param: Option<JsonElem>) -> Result<JsonElem, Error> {
+        match method {
+            "list_objects" => to_response(&self.list_objects()),
+            "list_clients" => to_response(&self.list_clients()),
+            "list_subscriptions" => to_response(&self.list_subscriptions()),
+            "describe_object" => {
+                let param: DescribeObject = param
+                    .and_then(|param| param.convert_to().ok())
+                    .ok_or_else(|| error(StaticReplies::InvalidParameter))?;
+                to_response(&self.describe_object(&param.object)?)
+            }
+            _ => Err(error(StaticReplies::MethodNotFound)),
+        }
+    }
+
+    /// The registered objects and their owner, by name.
+    fn list_objects(&self) -> Vec<ObjectSummary<'_>> {
+        let mut objects: Vec<ObjectSummary> = self
+            .objects
+            .iter()
+            .map(|(object, holder)| ObjectSummary {
+                object,
+                owner: holder.id,
+            })
+            .collect();
+        objects.sort_by(|a, b| a.object.cmp(b.object));
+        objects
+    }
+
+    /// The connected clients with the objects they share and the events they subscribe to, by id.
+    fn list_clients(&self) -> Vec<ClientDescription<'_>> {
+        let mut clients: Vec<ClientDescription> = self
+            .clients
+            .values()
+            .map(|info| {
+                let mut objects: Vec<&str> = self
+                    .objects
+                    .iter()
+                    .filter(|(_, holder)| holder.id == info.id)
+                    .map(|(object, _)| object.as_str())
+                    .collect();
+                objects.sort();
+                let mut subscriptions: Vec<&str> = self
+                    .subscribers
+                    .iter()
+                    .filter(|(_, holders)| holders.iter().any(|holder| holder.id == info.id))
+                    .map(|(event, _)| event.as_str())
+                    .collect();
+                subscriptions.sort();
+                ClientDescription {
+                    info,
+                    objects,
+                    subscriptions,
+                }
+            })
+            .collect();
+        clients.sort_by_key(|client| client.info.id);
+        clients
+    }
+
+    /// The clients subscribed to each event, by event name.
+    fn list_subscriptions(&self) -> BTreeMap<&str, Vec<ClientId>> {
+        self.subscribers
+            .iter()
+            .map(|(event, holders)| {
+                let mut ids: Vec<ClientId> = holders.iter().map(|holder| holder.id).collect();
+                ids.sort();
+                (event.as_str(), ids)
+            })
+            .collect()
+    }
+
+    /// The owner of the object, the clients on standby for it and its methods.
+    fn describe_object<'a>(&'a self, object: &'a str) -> Result<ObjectDescription<'a>, Error> {
+        let owner = self
+            .objects
+            .get(object)
+            .ok_or_else(|| error(StaticReplies::ObjectNotFound))?;
+        let standby = self.standby.get(object).map(Vec::as_slice).unwrap_or(&[]);
+        Ok(ObjectDescription {
+            object,
+            owner: self.client(owner),
+            standby: standby.iter().map(|holder| self.client(holder)).collect(),
+            methods: self.methods.get(&(object.to_string(), owner.id)),
+        })
+    }
+
+    /// What the server knows about the client of the socket.
+    fn client(&self, holder: &SocketHolder) -> ClientInfo {
+        self.clients
+            .get(&holder.id)
+            .cloned()
+            .unwrap_or_else(|| ClientInfo {
+                id: holder.id,
+                peer: holder.name.clone(),
+                name: None,
+            })
+    }
+}
+
+fn error(reply: StaticReplies) -> Error {
+    Error::new(JsonElem::String(reply.to_string()))
+}
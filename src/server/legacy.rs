@@ -0,0 +1,87 @@
+use serde_json::{Map, Value};
+
+use crate::client::message::PROTOCOL_VERSION;
+
+/// The field that identified each message before messages were wrapped in an envelope,
+/// in the order the untagged messages used to be matched.
+const LEGACY_FIELDS: [(&str, &str); 8] = [
+    ("reg_object", "register_object"),
+    ("object", "call_request"),
+    ("success", "success"),
+    ("list", "list_objects"),
+    ("event_name", "subscribe_to_event"),
+    ("event", "event"),
+    ("response", "call_response"),
+    ("error", "error"),
+];
+
+/// Wraps a message sent by a legacy client into an envelope.
+/// The message is returned as is if it is not recognized.
+pub fn upgrade(frame: Vec<u8>) -> Vec<u8> {
+    let payload = match serde_json::from_slice::<Map<String, Value>>(&frame) {
+        Ok(payload) => payload,
+        Err(_) => return frame,
+    };
+    let kind = match LEGACY_FIELDS
+        .iter()
+        .find(|(field, _)| payload.contains_key(*field))
+    {
+        Some((_, kind)) => kind,
+        None => return frame,
+    };
+
+    let mut envelope = Map::new();
+    envelope.insert("version".into(), PROTOCOL_VERSION.into());
+    envelope.insert("type".into(), (*kind).into());
+    envelope.insert("payload".into(), Value::Object(payload));
+    serde_json::to_vec(&envelope).unwrap_or(frame)
+}
+
+/// Unwraps the payload of an envelope for a legacy client.
+/// The null flag of a response, which legacy clients do not know, is left out, so they read "OK".
+pub fn downgrade(frame: Vec<u8>) -> Vec<u8> {
+    match serde_json::from_slice::<Map<String, Value>>(&frame) {
+        Ok(mut envelope) => match envelope.remove("payload") {
+            Some(mut payload) => {
+                if let Value::Object(fields) = &mut payload {
+                    if fields.contains_key("response") {
+                        fields.remove("null");
+                    }
+                }
+                serde_json::to_vec(&payload).unwrap_or(frame)
+            }
+            None => frame,
+        },
+        Err(_) => frame,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::legacy::{downgrade, upgrade};
+
+    #[test]
+    fn test_upgrade_and_downgrade() {
+        let msg = br#"{"reg_object":"mango"}"#.to_vec();
+        let upgraded = upgrade(msg.clone());
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&upgraded).unwrap(),
+            serde_json::json!({"version": 1, "type": "register_object", "payload": {"reg_object": "mango"}})
+        );
+        assert_eq!(downgrade(upgraded), msg);
+
+        let msg = br#"{"object":"mango","method":"login","param":{"event":"x"}}"#.to_vec();
+        let upgraded: serde_json::Value = serde_json::from_slice(&upgrade(msg)).unwrap();
+        assert_eq!(upgraded["type"], "call_request");
+
+        let response =
+            br#"{"version":1,"type":"call_response","payload":{"response":"OK","null":true}}"#;
+        assert_eq!(
+            downgrade(response.to_vec()),
+            br#"{"response":"OK"}"#.to_vec()
+        );
+
+        let msg = b"not json".to_vec();
+        assert_eq!(upgrade(msg.clone()), msg);
+    }
+}
@@ -0,0 +1,601 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use tokio::sync::{mpsc::UnboundedReceiver, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::client::{
+    error::Error,
+    message::{
+        CallObjectRequest, CallObjectResponse, Envelope, Event, IncomingMessage, MethodDescription,
+        RegisterPolicy, StaticReplies, Success, Welcome, EVENT_OBJECT_REGISTERED,
+        EVENT_OBJECT_UNREGISTERED, RESERVED_EVENT_PREFIX, RESERVED_OBJECT_PREFIX, SERVER_OBJECT,
+    },
+};
+
+use json_elem::jsonelem::JsonElem;
+
+use crate::server::introspection::Registry;
+use crate::server::message::{
+    ClientId, ClientInfo, IpcMessage, Message, PendingCall, SocketHolder, WaitingForObjects,
+};
+use crate::server::schema;
+
+/// An object that is responsible in handling request from the server.
+pub struct TaskManager;
+
+impl TaskManager {
+    /// Spawns the TaskManager in the background with tokio::select!() it handle
+    /// asynchronous request from the server and pass into different handling stations
+    /// depend on the type of Message that the server wants it to do.
+    /// It stops once every sender of the channel is dropped.
+    /// A call that is not answered within its timeout, or call_timeout if the caller
+    /// did not set one, fails with StaticReplies::CallTimeout.
+    /// With validate_params, the calls are checked against the methods their object describes.
+    pub async fn spawn(
+        mut rx: UnboundedReceiver<Message>,
+        call_timeout: Duration,
+        validate_params: bool,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut list_session = HashMap::new();
+            // The connected clients, by client id.
+            let mut list_clients: HashMap<ClientId, ClientInfo> = HashMap::new();
+            // The clients queued to take an object over once its owner is gone, by object.
+            let mut list_standby: HashMap<String, Vec<SocketHolder>> = HashMap::new();
+            // The methods described by the owner and the clients on standby, by object and client.
+            let mut list_methods: HashMap<(String, ClientId), Vec<MethodDescription>> =
+                HashMap::new();
+            let mut list_subscriber_for_event = HashMap::new();
+            let mut list_pending_call: HashMap<u64, PendingCall> = HashMap::new();
+            // The calls that timed out, by provider, until their late response arrives.
+            let mut list_expired_call: HashMap<u64, ClientId> = HashMap::new();
+            // The clients are answered once every object they wait for is registered.
+            let mut list_waiting_for_objects: Vec<WaitingForObjects> = Vec::new();
+            let mut deadlines: BTreeSet<(Instant, u64)> = BTreeSet::new();
+            let mut next_call_id: u64 = 1;
+            let mut draining = false;
+            let mut drained: Option<oneshot::Sender<()>> = None;
+            loop {
+                let next_deadline = deadlines.first().map(|(deadline, _)| *deadline);
+                tokio::select! {
+                    msg = rx.recv() => {
+                        let msg = match msg {
+                            Some(msg) => msg,
+                            None => break,
+                        };
+                        match msg {
+                            Message::AddClient(holder) => {
+                                list_clients.insert(holder.id, ClientInfo { id: holder.id, peer: holder.name, name: None });
+                            }
+                            Message::ProcessInput(session, tx) => {
+                                match session.msg {
+                                    IpcMessage::Register(data) => {
+                                        let client = session.socket_holder.id;
+                                        log::trace!("[{}]: {:?}", session.socket_holder, data);
+                                        let object = data.reg_object;
+                                        let owner = list_session.get(&object).map(|holder: &SocketHolder| holder.id);
+                                        let reply = match (owner, data.policy) {
+                                            _ if object.starts_with(RESERVED_OBJECT_PREFIX) => {
+                                                log::error!("[{}]: Reserved object {}", session.socket_holder, object);
+                                                IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::ReservedObject.to_string())))
+                                            }
+                                            (Some(owner), RegisterPolicy::Reject) if owner != client => {
+                                                log::error!("[{}]: {} is already registered by #{}", session.socket_holder, object, owner);
+                                                IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::AlreadyRegistered.to_string())))
+                                            }
+                                            (Some(owner), RegisterPolicy::Standby) if owner != client => {
+                                                log::trace!("[{}]: {} is on standby for #{}", session.socket_holder, object, owner);
+                                                set_methods(&mut list_methods, &object, client, data.methods);
+                                                let queue = list_standby.entry(object.clone()).or_default();
+                                                if queue.iter().all(|holder| holder.id != client) {
+                                                    queue.push(session.socket_holder);
+                                                }
+                                                IncomingMessage::Register(Success::new(StaticReplies::Standby.as_ref()))
+                                            }
+                                            (owner, _) => {
+                                                // The client no longer waits for the object it now owns.
+                                                remove_standby(&mut list_standby, client, Some(&object));
+                                                match owner {
+                                                    Some(owner) if owner == client => {}
+                                                    Some(owner) => {
+                                                        log::info!("[{}]: {} is taken over from #{}", session.socket_holder, object, owner);
+                                                        list_methods.remove(&(object.clone(), owner));
+                                                        let unregistered = registry_event(EVENT_OBJECT_UNREGISTERED, &object, owner);
+                                                        broadcast(&list_subscriber_for_event, unregistered.clone());
+                                                        broadcast(&list_subscriber_for_event, registry_event(EVENT_OBJECT_REGISTERED, &object, client));
+                                                        // The previous owner is told even if it did not subscribe, and the calls it
+                                                        // was forwarded fail, as its late responses are discarded.
+                                                        let subscribed = list_subscriber_for_event
+                                                            .get(EVENT_OBJECT_UNREGISTERED)
+                                                            .is_some_and(|holders| holders.iter().any(|holder| holder.id == owner));
+                                                        if let Some(holder) = list_session.get(&object).filter(|_| !subscribed) {
+                                                            holder.send(Envelope::new(IncomingMessage::Event(unregistered)));
+                                                        }
+                                                        let error = Error::new(JsonElem::String(StaticReplies::ObjectReplaced.to_string()));
+                                                        for id in fail_pending_calls(&mut list_pending_call, &mut deadlines, owner, Some(&object), &error) {
+                                                            list_expired_call.insert(id, owner);
+                                                        }
+                                                    }
+                                                    None => broadcast(&list_subscriber_for_event, registry_event(EVENT_OBJECT_REGISTERED, &object, client)),
+                                                }
+                                                log::trace!("[{}]: Shared objects: {:?}", session.socket_holder, list_session.keys());
+                                                set_methods(&mut list_methods, &object, client, data.methods);
+                                                list_session.insert(object, session.socket_holder);
+                                                notify_waiting_for_objects(&mut list_waiting_for_objects, &list_session);
+                                                IncomingMessage::Register(Success::new(StaticReplies::Ok.as_ref()))
+                                            }
+                                        };
+
+                                        tx.send(reply)
+                                            .unwrap_or_else(|e| {
+                                                log::error!("{:?}", e);
+                                            });
+                                    }
+                                    IpcMessage::Unregister(data) => {
+                                        let client = session.socket_holder.id;
+                                        log::trace!("[{}]: {:?}", session.socket_holder, data);
+                                        let object = data.unreg_object;
+                                        let on_standby = list_standby.get(&object).is_some_and(|queue| queue.iter().any(|holder| holder.id == client));
+                                        let reply = match list_session.get(&object) {
+                                            Some(holder) if holder.id == client => {
+                                                list_methods.remove(&(object.clone(), client));
+                                                remove_object(&object, &mut list_session, &mut list_standby, &list_subscriber_for_event);
+                                                log::trace!("[{}]: Shared objects: {:?}", session.socket_holder, list_session.keys());
+                                                notify_waiting_for_objects(&mut list_waiting_for_objects, &list_session);
+                                                IncomingMessage::Register(Success::new(StaticReplies::Ok.as_ref()))
+                                            }
+                                            _ if on_standby => {
+                                                list_methods.remove(&(object.clone(), client));
+                                                remove_standby(&mut list_standby, client, Some(&object));
+                                                IncomingMessage::Register(Success::new(StaticReplies::Ok.as_ref()))
+                                            }
+                                            Some(_) => IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::NotOwner.to_string()))),
+                                            None => IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::ObjectNotFound.to_string()))),
+                                        };
+                                        tx.send(reply).unwrap_or_else(|e| {
+                                            log::error!("{:?}", e);
+                                        });
+                                    }
+                                    IpcMessage::Call(request) => {
+                                        log::trace!("[{}]: {:?}", session.socket_holder, request);
+                                        if request.object == SERVER_OBJECT {
+                                            let registry = Registry {
+                                                objects: &list_session,
+                                                standby: &list_standby,
+                                                subscribers: &list_subscriber_for_event,
+                                                clients: &list_clients,
+                                                methods: &list_methods,
+                                            };
+                                            let reply = match registry.call(&request.method, request.param) {
+                                                Ok(response) => IncomingMessage::CallResponse(CallObjectResponse::new(response)),
+                                                Err(error) => IncomingMessage::Error(error),
+                                            };
+                                            tx.send(reply).unwrap_or_else(|e| {
+                                                log::error!("{:?}", e);
+                                            });
+                                        } else if draining {
+                                            tx.send(IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::ServerShuttingDown.to_string()))))
+                                                .unwrap_or_else(|e| {
+                                                    log::error!("{:?}", e);
+                                                });
+                                        } else if let Some(error) = list_session
+                                            .get(request.object.as_str())
+                                            .and_then(|s| list_methods.get(&(request.object.clone(), s.id)))
+                                            .filter(|_| validate_params)
+                                            .and_then(|methods| check_call(methods, &request))
+                                        {
+                                            log::error!("[{}]: Rejected call to {}.{}: {}", session.socket_holder, request.object, request.method, error);
+                                            tx.send(IncomingMessage::Error(error))
+                                                .unwrap_or_else(|e| {
+                                                    log::error!("{:?}", e);
+                                                });
+                                        } else if let Some(s) = list_session.get(request.object.as_str()) {
+                                            let id = next_call_id;
+                                            next_call_id += 1;
+                                            let timeout = request.timeout_ms.map(Duration::from_millis).unwrap_or(call_timeout);
+                                            let request_object = request.object.clone();
+                                            if TaskManager::handle_call_request(s, id, request) {
+                                                let deadline = Instant::now() + timeout;
+                                                deadlines.insert((deadline, id));
+                                                list_pending_call.insert(id, PendingCall { provider: s.id, object: request_object, reply: tx, deadline });
+                                            } else {
+                                                // If Destination process cannot be reached, better remove it from the list.
+                                                let provider = s.id;
+                                                remove_objects(&mut list_session, &mut list_standby, &list_subscriber_for_event, provider);
+                                                log::trace!("[#{}]: Shared objects: {:?}", provider, list_session.keys());
+                                                notify_waiting_for_objects(&mut list_waiting_for_objects, &list_session);
+
+                                                tx.send(IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::ClientConnectionError.to_string()))))
+                                                    .unwrap_or_else(|e| {
+                                                        log::error!("{:?}", e);
+                                                    });
+                                            }
+                                        } else {
+                                            tx.send(IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::ObjectNotFound.to_string()))))
+                                                .unwrap_or_else(|e| {
+                                                    log::error!("{:?}", e);
+                                                });
+                                        }
+                                    }
+                                    IpcMessage::WaitForObjects(request) => {
+                                        if request.list.iter().all(|item| list_session.contains_key(item)) {
+                                            log::trace!("[{}]: {:?} object are available.", session.socket_holder, request);
+                                            tx.send(IncomingMessage::WaitForObjects(request)).unwrap_or_else(|e| {
+                                                log::error!("{:?}", e);
+                                            });
+                                        } else {
+                                            log::trace!("[{}]: {:?} object not yet available.", session.socket_holder, request);
+                                            list_waiting_for_objects.push(WaitingForObjects { client: session.socket_holder.id, request, reply: tx });
+                                        }
+                                    }
+                                    IpcMessage::AddToEventList(add_to_event) => {
+                                        log::trace!("{} has subscribe for events.", session.socket_holder);
+                                        insert_or_update(&mut list_subscriber_for_event, add_to_event.event_name.as_str(), session.socket_holder);
+
+                                        log::trace!("Subscriber List: {:?}", list_subscriber_for_event);
+                                        // Legacy clients send no id and do not wait for a reply.
+                                        if session.id.is_some() {
+                                            tx.send(IncomingMessage::Register(Success::new(StaticReplies::Ok.as_ref())))
+                                                .unwrap_or_else(|e| {
+                                                    log::error!("{:?}", e);
+                                                });
+                                        }
+                                    }
+                                    IpcMessage::RemoveFromEventList(remove_from_event) => {
+                                        log::trace!("{} has unsubscribe from {}.", session.socket_holder, remove_from_event.event_name);
+                                        remove_subscriber(&mut list_subscriber_for_event, &remove_from_event.event_name, session.socket_holder.id);
+
+                                        log::trace!("Subscriber List: {:?}", list_subscriber_for_event);
+                                    }
+
+                                    IpcMessage::BroadCastEvent(event) if event.event.starts_with(RESERVED_EVENT_PREFIX) => {
+                                        log::error!("[{}]: Reserved event {}", session.socket_holder, event.event);
+                                        tx.send(IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::ReservedEvent.to_string()))))
+                                            .unwrap_or_else(|e| {
+                                                log::error!("{:?}", e);
+                                            });
+                                    }
+                                    IpcMessage::BroadCastEvent(event) => {
+                                        broadcast(&list_subscriber_for_event, event);
+                                        tx.send(IncomingMessage::Register(Success::new(StaticReplies::Ok.as_ref())))
+                                        .unwrap_or_else(|e| {
+                                            log::error!("{:?}", e);
+                                        });
+                                    }
+                                    IpcMessage::CallResponse(_) | IpcMessage::Error(_) => {
+                                        let provider = session.socket_holder.id;
+                                        // Legacy processes answer without an id, one call at a time.
+                                        let id = session.id.or_else(|| {
+                                            let pending = list_pending_call.iter().map(|(id, call)| (id, &call.provider));
+                                            pending.chain(list_expired_call.iter()).filter(|(_, p)| **p == provider).map(|(id, _)| *id).min()
+                                        });
+                                        let expired = id.is_some_and(|id| list_expired_call.get(&id) == Some(&provider));
+                                        if expired {
+                                            id.map(|id| list_expired_call.remove(&id));
+                                        }
+                                        let call = match id {
+                                            Some(id) if list_pending_call.get(&id).is_some_and(|call| call.provider == provider) => list_pending_call.remove(&id),
+                                            _ => None,
+                                        };
+                                        let response = match session.msg {
+                                            IpcMessage::CallResponse(response) => IncomingMessage::CallResponse(response),
+                                            IpcMessage::Error(error) => IncomingMessage::Error(error),
+                                            _ => unreachable!(),
+                                        };
+                                        if let (Some(id), Some(call)) = (id, &call) {
+                                            deadlines.remove(&(call.deadline, id));
+                                        }
+                                        match call {
+                                            // Forward the response of the call object back to the calling process
+                                            Some(call) => call.reply.send(response).unwrap_or_else(|e| {
+                                                log::error!("{:?}", e);
+                                            }),
+                                            None if expired => log::trace!("[{}]: Discarding late response: {:?}", session.socket_holder, response),
+                                            None => log::trace!("[{}]: Discarding unexpected response: {:?}", session.socket_holder, response),
+                                        }
+                                    }
+                                    IpcMessage::Hello(hello) => {
+                                        let client = session.socket_holder.id;
+                                        if let Some(name) = &hello.name {
+                                            log::info!("[{}]: Client is {}", session.socket_holder, name);
+                                        }
+                                        if let Some(info) = list_clients.get_mut(&client) {
+                                            info.name = hello.name.clone();
+                                        }
+                                        tx.send(IncomingMessage::Welcome(Welcome::new(client, hello.name)))
+                                            .unwrap_or_else(|e| {
+                                                log::error!("{:?}", e);
+                                            });
+                                    }
+                                    _ => {
+                                        log::error!("Unhandled Message: {:?}", session.msg);
+                                    }
+                                }
+                            },
+                            Message::RemoveRegistered(session) => {
+                                log::trace!("{:?}", session);
+                                let client = session.socket_holder.id;
+                                remove_socket(&mut list_subscriber_for_event, client);
+                                log::trace!("{} has unsubscribe from events.", session.socket_holder);
+                                log::trace!("Subscriber List: {:?}", list_subscriber_for_event);
+
+                                remove_objects(&mut list_session, &mut list_standby, &list_subscriber_for_event, client);
+                                notify_waiting_for_objects(&mut list_waiting_for_objects, &list_session);
+                                list_expired_call.retain(|_, provider| *provider != client);
+                                list_waiting_for_objects.retain(|waiting| waiting.client != client);
+                                list_clients.remove(&client);
+                                list_methods.retain(|(_, owner), _| *owner != client);
+                                log::trace!("[{}]: Shared objects: {:?}", session.socket_holder, list_session.keys());
+
+                                // The providers are disconnected by the server itself once the shutdown deadline expires.
+                                let reply = if draining { StaticReplies::ServerShuttingDown } else { StaticReplies::ClientConnectionError };
+                                let error = Error::new(JsonElem::String(reply.to_string()));
+                                fail_pending_calls(&mut list_pending_call, &mut deadlines, client, None, &error);
+                            }
+                            Message::Drain(tx) => {
+                                log::info!("Draining {} calls in flight", list_pending_call.len());
+                                draining = true;
+                                drained = Some(tx);
+                            }
+                        }
+                    },
+                    _ = tokio::time::sleep_until(next_deadline.unwrap_or_else(Instant::now)), if next_deadline.is_some() => {
+                        let now = Instant::now();
+                        while let Some((_, id)) = deadlines.first().copied().filter(|(deadline, _)| *deadline <= now) {
+                            deadlines.pop_first();
+                            if let Some(call) = list_pending_call.remove(&id) {
+                                log::error!("[#{}]: Call {} timed out", call.provider, id);
+                                call.reply.send(IncomingMessage::Error(Error::new(JsonElem::String(StaticReplies::CallTimeout.to_string()))))
+                                    .unwrap_or_else(|e| {
+                                        log::error!("{:?}", e);
+                                    });
+                                list_expired_call.insert(id, call.provider);
+                            }
+                        }
+                    },
+                }
+
+                if list_pending_call.is_empty() {
+                    if let Some(tx) = drained.take() {
+                        let _ = tx.send(());
+                    }
+                }
+            }
+        })
+    }
+
+    /// This forwards a remote object call method request from other process to the process
+    /// that shares the object. The response comes back later as a message from that process
+    /// with the same id, so the TaskManager never waits for the destination process.
+    /// Returns false if the destination process cannot be reached.
+    fn handle_call_request(holder: &SocketHolder, id: u64, request: CallObjectRequest) -> bool {
+        holder.send(Envelope::new(IncomingMessage::CallRequest(request)).with_id(Some(id)))
+    }
+}
+
+fn insert_or_update(map: &mut HashMap<String, Vec<SocketHolder>>, key: &str, value: SocketHolder) {
+    let entry = map.entry(key.to_string());
+
+    match entry {
+        std::collections::hash_map::Entry::Occupied(mut e) => {
+            if e.get().iter().all(|x| x.id != value.id) {
+                e.get_mut().push(value);
+            }
+        }
+        std::collections::hash_map::Entry::Vacant(e) => {
+            e.insert(vec![value]);
+        }
+    }
+}
+
+fn remove_socket(map: &mut HashMap<String, Vec<SocketHolder>>, client: ClientId) {
+    for (key, _value) in map.clone() {
+        if let Some(values) = map.get_mut(&key) {
+            values.retain(|x| x.id != client);
+
+            if values.is_empty() {
+                map.remove(&key);
+            }
+        }
+    }
+}
+
+/// Removes the client from the subscribers of the event.
+fn remove_subscriber(map: &mut HashMap<String, Vec<SocketHolder>>, key: &str, client: ClientId) {
+    if let Some(values) = map.get_mut(key) {
+        values.retain(|x| x.id != client);
+
+        if values.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+/// Sends the event to its subscribers.
+fn broadcast(subscribers: &HashMap<String, Vec<SocketHolder>>, event: Event) {
+    if let Some(list_socket_holder) = subscribers.get(&event.event) {
+        for holder in list_socket_holder {
+            log::trace!("Broadcasting this event to -> {}", holder);
+            holder.send(Envelope::new(IncomingMessage::Event(event.clone())));
+        }
+    }
+}
+
+/// The event that tells the subscribers about a change of the shared objects.
+fn registry_event(name: &str, object: &str, owner: ClientId) -> Event {
+    let result = HashMap::from([
+        ("object".to_string(), JsonElem::String(object.to_string())),
+        ("owner".to_string(), client_id(owner)),
+    ]);
+    Event::new(name, JsonElem::HashMap(result))
+}
+
+/// Keeps the methods the client described for the object, or forgets them if it described none.
+fn set_methods(
+    list_methods: &mut HashMap<(String, ClientId), Vec<MethodDescription>>,
+    object: &str,
+    client: ClientId,
+    methods: Option<Vec<MethodDescription>>,
+) {
+    let key = (object.to_string(), client);
+    match methods {
+        Some(methods) => list_methods.insert(key, methods),
+        None => list_methods.remove(&key),
+    };
+}
+
+/// Checks the call against the methods its object describes.
+/// Returns the error of the call if it would be rejected by the object.
+fn check_call(methods: &[MethodDescription], request: &CallObjectRequest) -> Option<Error> {
+    let method = match methods.iter().find(|method| method.name == request.method) {
+        Some(method) => method,
+        None => {
+            return Some(Error::new(JsonElem::String(
+                StaticReplies::MethodNotFound.to_string(),
+            )))
+        }
+    };
+    let schema = method.params.as_ref()?;
+    schema::validate(schema, request.param.as_ref())
+        .err()
+        .map(|reason| {
+            Error::new(JsonElem::String(format!(
+                "{}: {}",
+                StaticReplies::InvalidParameter,
+                reason
+            )))
+        })
+}
+
+/// The client id as a JsonElem, whose integers are only 32 bits wide.
+fn client_id(id: ClientId) -> JsonElem {
+    i32::try_from(id)
+        .map(JsonElem::Integer)
+        .unwrap_or(JsonElem::Float(id as f64))
+}
+
+/// Removes the objects shared by the provider, and every queue it stands by in.
+fn remove_objects(
+    list_session: &mut HashMap<String, SocketHolder>,
+    list_standby: &mut HashMap<String, Vec<SocketHolder>>,
+    subscribers: &HashMap<String, Vec<SocketHolder>>,
+    provider: ClientId,
+) {
+    remove_standby(list_standby, provider, None);
+    let objects: Vec<String> = list_session
+        .iter()
+        .filter(|(_, holder)| holder.id == provider)
+        .map(|(object, _)| object.clone())
+        .collect();
+    for object in objects {
+        remove_object(&object, list_session, list_standby, subscribers);
+    }
+}
+
+/// Removes the object and hands it over to the first client on standby for it,
+/// and tells the subscribers.
+fn remove_object(
+    object: &str,
+    list_session: &mut HashMap<String, SocketHolder>,
+    list_standby: &mut HashMap<String, Vec<SocketHolder>>,
+    subscribers: &HashMap<String, Vec<SocketHolder>>,
+) {
+    if let Some(owner) = list_session.remove(object) {
+        broadcast(
+            subscribers,
+            registry_event(EVENT_OBJECT_UNREGISTERED, object, owner.id),
+        );
+    }
+    let next = match list_standby.get_mut(object) {
+        Some(queue) if !queue.is_empty() => Some(queue.remove(0)),
+        _ => None,
+    };
+    if list_standby
+        .get(object)
+        .is_some_and(|queue| queue.is_empty())
+    {
+        list_standby.remove(object);
+    }
+    if let Some(next) = next {
+        log::info!("[{}]: {} is taken over from standby", next, object);
+        broadcast(
+            subscribers,
+            registry_event(EVENT_OBJECT_REGISTERED, object, next.id),
+        );
+        list_session.insert(object.to_string(), next);
+    }
+}
+
+/// Removes the client from the standby queue of the object, or of every object.
+fn remove_standby(
+    list_standby: &mut HashMap<String, Vec<SocketHolder>>,
+    client: ClientId,
+    object: Option<&str>,
+) {
+    list_standby.retain(|name, queue| {
+        if object.is_none_or(|object| object == name) {
+            queue.retain(|holder| holder.id != client);
+        }
+        !queue.is_empty()
+    });
+}
+
+/// Answers the clients whose objects are now all registered.
+fn notify_waiting_for_objects(
+    waiting: &mut Vec<WaitingForObjects>,
+    list_session: &HashMap<String, SocketHolder>,
+) {
+    let (ready, still_waiting) = std::mem::take(waiting).into_iter().partition(|waiting| {
+        waiting
+            .request
+            .list
+            .iter()
+            .all(|item| list_session.contains_key(item))
+    });
+    *waiting = still_waiting;
+
+    for waiting in ready {
+        log::trace!(
+            "[{}]: {:?} object are available.",
+            waiting.client,
+            waiting.request
+        );
+        waiting
+            .reply
+            .send(IncomingMessage::WaitForObjects(waiting.request))
+            .unwrap_or_else(|e| {
+                log::error!("{:?}", e);
+            });
+    }
+}
+
+/// Fails the calls forwarded to the provider, for the object or for every object.
+/// Returns the ids of the calls.
+fn fail_pending_calls(
+    map: &mut HashMap<u64, PendingCall>,
+    deadlines: &mut BTreeSet<(Instant, u64)>,
+    provider: ClientId,
+    object: Option<&str>,
+    error: &Error,
+) -> Vec<u64> {
+    let ids: Vec<u64> = map
+        .iter()
+        .filter(|(_, call)| call.provider == provider)
+        .filter(|(_, call)| object.is_none_or(|object| object == call.object))
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in &ids {
+        if let Some(call) = map.remove(id) {
+            deadlines.remove(&(call.deadline, *id));
+            call.reply
+                .send(IncomingMessage::Error(error.clone()))
+                .unwrap_or_else(|e| {
+                    log::error!("{:?}", e);
+                });
+        }
+    }
+    ids
+}
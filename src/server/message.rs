@@ -0,0 +1,253 @@
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::{mpsc::UnboundedSender, oneshot::Sender};
+use tokio::time::Instant;
+
+use crate::client::error::Error;
+use crate::client::message::{
+    CallObjectRequest, CallObjectResponse, Envelope, Event, Hello, IncomingMessage, ListObjects,
+    RegisterObject, SubscribeToEvent, Success, UnregisterObject, UnsubscribeFromEvent,
+};
+
+/// A list of Message if the message received by the Server needs some processing or
+/// need to removed a registered object.
+#[derive(Debug)]
+pub enum Message {
+    /// A client is connected, before any of its messages.
+    AddClient(SocketHolder),
+    ProcessInput(Session, Sender<IncomingMessage>),
+    RemoveRegistered(Session),
+    /// Rejects new calls and signals once every call in flight is answered.
+    Drain(Sender<()>),
+}
+
+/// The unique id the server gives to each connection when it accepts it.
+pub type ClientId = u64;
+
+/// Stores the id of the connection, the peer name of the socket and the queue
+/// of the messages to be written to it by its writer task.
+#[derive(Clone)]
+pub struct SocketHolder {
+    pub id: ClientId,
+    /// The peer address of the socket, which is not unique across transports.
+    pub name: String,
+    pub tx: UnboundedSender<Envelope<IncomingMessage>>,
+}
+
+impl std::fmt::Debug for SocketHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SocketHolder")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for SocketHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} {}", self.id, self.name)
+    }
+}
+
+impl SocketHolder {
+    /// Queues the message to be written to the socket.
+    /// Returns false if the connection is already closed.
+    pub fn send(&self, message: Envelope<IncomingMessage>) -> bool {
+        self.tx
+            .send(message)
+            .map_err(|e| {
+                log::error!("[{}]: {:?}", self, e);
+            })
+            .is_ok()
+    }
+}
+
+/// A connected client, as told by the introspection object of the server.
+#[derive(Serialize, Debug, Clone)]
+pub struct ClientInfo {
+    pub id: ClientId,
+    /// The peer address of the socket.
+    pub peer: String,
+    /// The display name the client gave in its hello.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// A call that was forwarded to the process sharing the object and is waiting for its response.
+#[derive(Debug)]
+pub struct PendingCall {
+    pub provider: ClientId,
+    pub object: String,
+    pub reply: Sender<IncomingMessage>,
+    /// The call fails with StaticReplies::CallTimeout if it is not answered by then.
+    pub deadline: Instant,
+}
+
+/// A client waiting for objects that are not all registered yet.
+#[derive(Debug)]
+pub struct WaitingForObjects {
+    pub client: ClientId,
+    pub request: ListObjects,
+    pub reply: Sender<IncomingMessage>,
+}
+
+/// Stores the type of IpcMessage, its request id and the socket of the calling process.
+#[derive(Debug)]
+pub struct Session {
+    pub id: Option<u64>,
+    pub msg: IpcMessage,
+    pub socket_holder: SocketHolder,
+}
+
+/// The id of a message whose envelope cannot be read, so its error still reaches the request.
+#[derive(Deserialize, Debug)]
+pub struct MessageId {
+    #[serde(default)]
+    pub id: Option<u64>,
+}
+
+/// A list of possible intercepted messages by the IPC server
+/// to handle the specific tasks correctly into the TaskManager.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "payload")]
+pub enum IpcMessage {
+    #[serde(skip)]
+    None,
+    #[serde(rename = "register_object")]
+    Register(RegisterObject),
+    #[serde(rename = "unregister_object")]
+    Unregister(UnregisterObject),
+    #[serde(rename = "call_request")]
+    Call(CallObjectRequest),
+    #[serde(rename = "call_response")]
+    CallResponse(CallObjectResponse),
+    #[serde(rename = "error")]
+    Error(Error),
+    #[serde(rename = "success")]
+    Success(Success),
+    #[serde(rename = "list_objects")]
+    WaitForObjects(ListObjects),
+    #[serde(rename = "subscribe_to_event")]
+    AddToEventList(SubscribeToEvent),
+    #[serde(rename = "unsubscribe_from_event")]
+    RemoveFromEventList(UnsubscribeFromEvent),
+    #[serde(rename = "event")]
+    BroadCastEvent(Event),
+    #[serde(rename = "hello")]
+    Hello(Hello),
+}
+
+impl Session {
+    /// Create a new Session() object to store the IpcMessage type, the id of the request
+    /// and the socket holder of the process where the message came from.
+    pub fn new(id: Option<u64>, msg: IpcMessage, socket_holder: SocketHolder) -> Self {
+        Self {
+            id,
+            msg,
+            socket_holder,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::client::message::{Envelope, PROTOCOL_VERSION};
+    use json_elem::jsonelem::JsonElem;
+
+    use crate::server::message::IpcMessage;
+
+    fn parse(msg: &str) -> IpcMessage {
+        let envelope: Envelope<IpcMessage> = serde_json::from_str(msg).unwrap();
+        assert_eq!(envelope.version, PROTOCOL_VERSION);
+        envelope.message
+    }
+
+    #[test]
+    fn test_ipc_message() {
+        let msg =
+            r#"{"version":1,"type":"event","payload":{"event":"your event here","result":{}}}"#;
+        if let IpcMessage::BroadCastEvent(event) = parse(msg) {
+            assert_eq!(event.event, "your event here");
+            assert_eq!(event.result, JsonElem::HashMap(HashMap::new()));
+        } else {
+            panic!("This must be IpcMessage::BroadCastEvent");
+        }
+
+        let msg = r#"{"version":1,"type":"subscribe_to_event","payload":{"event_name":"your event here"}}"#;
+        if let IpcMessage::AddToEventList(event) = parse(msg) {
+            assert_eq!(event.event_name, "your event here");
+        } else {
+            panic!("This must be IpcMessage::AddToEventList");
+        }
+
+        let msg = r#"{"version":1,"type":"unsubscribe_from_event","payload":{"event_name":"your event here"}}"#;
+        if let IpcMessage::RemoveFromEventList(event) = parse(msg) {
+            assert_eq!(event.event_name, "your event here");
+        } else {
+            panic!("This must be IpcMessage::RemoveFromEventList");
+        }
+
+        let msg =
+            r#"{"version":1,"type":"register_object","payload":{"reg_object":"object name"}}"#;
+        if let IpcMessage::Register(obj) = parse(msg) {
+            assert_eq!(obj.reg_object, "object name");
+        } else {
+            panic!("This must be IpcMessage::Register");
+        }
+
+        let msg =
+            r#"{"version":1,"type":"unregister_object","payload":{"unreg_object":"object name"}}"#;
+        if let IpcMessage::Unregister(obj) = parse(msg) {
+            assert_eq!(obj.unreg_object, "object name");
+        } else {
+            panic!("This must be IpcMessage::Unregister");
+        }
+
+        let msg = r#"{"version":1,"id":7,"type":"call_request","payload":{"object":"object name","method":"method name","param":{"key":"parameter"}}}"#;
+        let envelope: Envelope<IpcMessage> = serde_json::from_str(msg).unwrap();
+        assert_eq!(envelope.id, Some(7));
+        if let IpcMessage::Call(obj) = envelope.message {
+            let mut hash = HashMap::new();
+
+            hash.insert("key".into(), JsonElem::String("parameter".into()));
+            assert_eq!(obj.object, "object name");
+            assert_eq!(obj.method, "method name");
+            assert_eq!(obj.param, Some(JsonElem::HashMap(hash)));
+        } else {
+            panic!("This must be IpcMessage::Call");
+        }
+
+        let msg =
+            r#"{"version":1,"type":"list_objects","payload":{"list":["mango","apple","orange"]}}"#;
+        if let IpcMessage::WaitForObjects(list) = parse(msg) {
+            assert_eq!(
+                list.list,
+                vec![
+                    "mango".to_string(),
+                    "apple".to_string(),
+                    "orange".to_string()
+                ]
+            );
+        } else {
+            panic!("This must be IpcMessage::WaitForObjects");
+        }
+
+        let msg = r#"{"version":1,"type":"success","payload":{"success":"OK"}}"#;
+        if let IpcMessage::Success(success) = parse(msg) {
+            assert_eq!(success.success, "OK".to_string());
+        } else {
+            panic!("This must be IpcMessage::WaitForObjects");
+        }
+    }
+
+    #[test]
+    fn test_ambiguous_payload() {
+        // A call whose parameter looks like another message is still a call.
+        let msg = r#"{"version":1,"type":"call_request","payload":{"object":"mango","method":"login","param":{"list":[],"event":"x"}}}"#;
+        assert!(matches!(parse(msg), IpcMessage::Call(_)));
+
+        let msg = r#"{"version":1,"type":"unknown","payload":{}}"#;
+        assert!(serde_json::from_str::<Envelope<IpcMessage>>(msg).is_err());
+    }
+}
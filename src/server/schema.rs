@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use json_elem::jsonelem::JsonElem;
+
+/// Checks the value against a JSON Schema, where None stands for null.
+/// The keywords `type`, `enum`, `anyOf`, `properties`, `required`, `additionalProperties`,
+/// `items`, `minimum` and `maximum` are checked, the other ones are ignored.
+/// Returns what is wrong with the value and where.
+pub fn validate(schema: &JsonElem, value: Option<&JsonElem>) -> Result<(), String> {
+    check(schema, value, "param")
+}
+
+fn check(schema: &JsonElem, value: Option<&JsonElem>, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        JsonElem::Bool(true) => return Ok(()),
+        JsonElem::Bool(false) => return Err(format!("{} is not allowed", path)),
+        JsonElem::HashMap(schema) => schema,
+        _ => return Ok(()),
+    };
+
+    match schema.get("type") {
+        Some(JsonElem::String(kind)) if !is_type(kind, value) => {
+            return Err(format!("{} must be of type {}", path, kind));
+        }
+        Some(JsonElem::Vec(kinds)) => {
+            let matches = kinds.iter().any(|kind| match kind {
+                JsonElem::String(kind) => is_type(kind, value),
+                _ => false,
+            });
+            if !matches {
+                return Err(format!("{} must be of type {}", path, to_json(kinds)));
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(JsonElem::Vec(values)) = schema.get("enum") {
+        if !value.is_some_and(|value| values.contains(value)) {
+            return Err(format!("{} must be one of {}", path, to_json(values)));
+        }
+    }
+
+    if let Some(JsonElem::Vec(schemas)) = schema.get("anyOf") {
+        let errors: Vec<String> = schemas
+            .iter()
+            .filter_map(|schema| check(schema, value, path).err())
+            .collect();
+        if errors.len() == schemas.len() {
+            return Err(errors.join(", or "));
+        }
+    }
+
+    if let Some(number) = value.and_then(as_number) {
+        if let Some(minimum) = schema.get("minimum").and_then(as_number) {
+            if number < minimum {
+                return Err(format!("{} must be at least {}", path, minimum));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(as_number) {
+            if number > maximum {
+                return Err(format!("{} must be at most {}", path, maximum));
+            }
+        }
+    }
+
+    match value {
+        Some(JsonElem::HashMap(object)) => check_object(schema, object, path),
+        Some(JsonElem::Vec(items)) => match schema.get("items") {
+            Some(items_schema) => items.iter().enumerate().try_for_each(|(i, item)| {
+                check(items_schema, Some(item), &format!("{}[{}]", path, i))
+            }),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn check_object(
+    schema: &HashMap<String, JsonElem>,
+    object: &HashMap<String, JsonElem>,
+    path: &str,
+) -> Result<(), String> {
+    if let Some(JsonElem::Vec(required)) = schema.get("required") {
+        for name in required {
+            if let JsonElem::String(name) = name {
+                if !object.contains_key(name) {
+                    return Err(format!("{}.{} is required", path, name));
+                }
+            }
+        }
+    }
+
+    let properties = match schema.get("properties") {
+        Some(JsonElem::HashMap(properties)) => Some(properties),
+        _ => None,
+    };
+    for (name, value) in object {
+        let path = format!("{}.{}", path, name);
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(property) => check(property, Some(value), &path)?,
+            None => {
+                if let Some(additional) = schema.get("additionalProperties") {
+                    check(additional, Some(value), &path)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the value is of the JSON Schema type.
+fn is_type(kind: &str, value: Option<&JsonElem>) -> bool {
+    match (kind, value) {
+        ("null", None) => true,
+        ("boolean", Some(JsonElem::Bool(_))) => true,
+        ("integer", Some(JsonElem::Integer(_))) => true,
+        ("integer", Some(JsonElem::Float(number))) => number.fract() == 0.0,
+        ("number", Some(JsonElem::Integer(_) | JsonElem::Float(_))) => true,
+        ("string", Some(JsonElem::String(_))) => true,
+        ("array", Some(JsonElem::Vec(_))) => true,
+        ("object", Some(JsonElem::HashMap(_))) => true,
+        _ => false,
+    }
+}
+
+fn to_json(values: &[JsonElem]) -> String {
+    serde_json::to_string(values).unwrap_or_default()
+}
+
+fn as_number(value: &JsonElem) -> Option<f64> {
+    match value {
+        JsonElem::Integer(number) => Some(*number as f64),
+        JsonElem::Float(number) => Some(*number),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json_elem::jsonelem::JsonElem;
+
+    use crate::server::schema::validate;
+
+    fn json(value: &str) -> JsonElem {
+        serde_json::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = json(
+            r#"{
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "age": {"type": "integer", "minimum": 0},
+                    "tags": {"type": "array", "items": {"enum": ["ripe", "green"]}}
+                },
+                "required": ["name"],
+                "additionalProperties": false
+            }"#,
+        );
+        assert_eq!(
+            validate(&schema, Some(&json(r#"{"name": "mango"}"#))),
+            Ok(())
+        );
+        assert_eq!(
+            validate(
+                &schema,
+                Some(&json(r#"{"name": "mango", "age": 3, "tags": ["ripe"]}"#))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&schema, Some(&json(r#"{"age": 3}"#))),
+            Err("param.name is required".to_string())
+        );
+        assert_eq!(
+            validate(&schema, Some(&json(r#"{"name": 3}"#))),
+            Err("param.name must be of type string".to_string())
+        );
+        assert_eq!(
+            validate(&schema, Some(&json(r#"{"name": "mango", "age": -1}"#))),
+            Err("param.age must be at least 0".to_string())
+        );
+        assert_eq!(
+            validate(
+                &schema,
+                Some(&json(r#"{"name": "mango", "tags": ["rotten"]}"#))
+            ),
+            Err(r#"param.tags[0] must be one of ["ripe","green"]"#.to_string())
+        );
+        assert_eq!(
+            validate(&schema, Some(&json(r#"{"name": "mango", "color": "red"}"#))),
+            Err("param.color is not allowed".to_string())
+        );
+        assert_eq!(
+            validate(&schema, None),
+            Err("param must be of type object".to_string())
+        );
+
+        assert_eq!(validate(&json(r#"{"type": "null"}"#), None), Ok(()));
+        assert_eq!(
+            validate(&json(r#"{"type": ["string", "null"]}"#), None),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&json(r#"{"type": "integer"}"#), Some(&json("2.0"))),
+            Ok(())
+        );
+        assert_eq!(validate(&json("{}"), Some(&json("[1, 2]"))), Ok(()));
+
+        let schema = json(r#"{"anyOf": [{"type": "string"}, {"type": "null"}]}"#);
+        assert_eq!(validate(&schema, None), Ok(()));
+        assert_eq!(
+            validate(&schema, Some(&json("1"))),
+            Err("param must be of type string, or param must be of type null".to_string())
+        );
+    }
+}
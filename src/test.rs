@@ -1,2 +1,4 @@
 mod call_object_request;
+#[cfg(target_os = "linux")]
+mod idle_clients;
 mod server;
@@ -2,9 +2,15 @@ use std::collections::HashMap;
 
 use json_elem::jsonelem::JsonElem;
 
-use ipc_client::client::message::CallObjectRequest;
-
-use crate::{message::IpcMessage, setup_logger};
+use crate::client::message::{CallObjectRequest, Envelope, OutgoingMessage};
+use crate::logger::setup_logger;
+use crate::server::message::IpcMessage;
+
+fn to_ipc_message(obj: CallObjectRequest) -> IpcMessage {
+    let data = OutgoingMessage::CallRequest(obj).serialize().unwrap();
+    let envelope: Envelope<IpcMessage> = serde_json::from_slice(data.as_slice()).unwrap();
+    envelope.message
+}
 
 #[test]
 fn test_call_object_request() {
@@ -14,7 +20,8 @@ fn test_call_object_request() {
     hash.insert("key1".to_string(), JsonElem::String("value1".into()));
     let obj = CallObjectRequest::new("object", "method", Some(JsonElem::HashMap(hash)));
 
-    let ipc: IpcMessage = serde_json::from_slice(obj.serialize().unwrap().as_slice()).unwrap();
+    let ipc = to_ipc_message(obj);
+    assert!(matches!(ipc, IpcMessage::Call(_)));
     log::trace!("{:?}", ipc);
 
     let mut hash = HashMap::new();
@@ -22,11 +29,13 @@ fn test_call_object_request() {
     hash.insert("process".to_string(), JsonElem::String("process".into()));
     let obj = CallObjectRequest::new("object", "method", Some(JsonElem::HashMap(hash)));
 
-    let ipc: IpcMessage = serde_json::from_slice(obj.serialize().unwrap().as_slice()).unwrap();
+    let ipc = to_ipc_message(obj);
+    assert!(matches!(ipc, IpcMessage::Call(_)));
     log::trace!("{:?}", ipc);
 
     let obj = CallObjectRequest::new("object", "method", None);
 
-    let ipc: IpcMessage = serde_json::from_slice(obj.serialize().unwrap().as_slice()).unwrap();
+    let ipc = to_ipc_message(obj);
+    assert!(matches!(ipc, IpcMessage::Call(_)));
     log::trace!("{:?}", ipc);
 }
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::runtime::Builder;
+
+use crate::client::frame::FramedStream;
+use crate::client::message::{ListObjects, OutgoingMessage};
+use crate::server::ServerBuilder;
+use crate::transport::Address;
+
+const IDLE_CLIENTS: usize = 200;
+
+/// Returns the CPU time spent by a thread of this process, in clock ticks.
+fn thread_cpu_ticks(tid: &str) -> u64 {
+    let stat = std::fs::read_to_string(format!("/proc/self/task/{}/stat", tid)).unwrap();
+    // The fields after the command name, which is enclosed in parentheses.
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let utime: u64 = fields[11].parse().unwrap();
+    let stime: u64 = fields[12].parse().unwrap();
+    utime + stime
+}
+
+#[test]
+fn test_idle_clients_cost_no_cpu() {
+    let (address_tx, address_rx) = std::sync::mpsc::channel();
+
+    // A server of its own, so the other tests do not keep it busy.
+    std::thread::spawn(move || {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async move {
+            let tid = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+            let tid = tid.split(' ').next().unwrap().to_string();
+
+            let server = ServerBuilder::new()
+                .bind(Address::Tcp("127.0.0.1:0".into()))
+                .spawn()
+                .await
+                .unwrap();
+            address_tx.send((server.address().clone(), tid)).unwrap();
+            server.wait().await;
+        });
+    });
+    let (address, tid) = address_rx.recv().unwrap();
+    let address = address.to_string();
+
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async move {
+        let mut clients = Vec::new();
+        for _n in 0..IDLE_CLIENTS {
+            let mut client = FramedStream::new(TcpStream::connect(&address).await.unwrap());
+            let request = OutgoingMessage::WaitForObjects(ListObjects::new(Vec::new()));
+            client
+                .write_frame(&request.serialize().unwrap())
+                .await
+                .unwrap();
+            client.read_frame().await.unwrap().unwrap();
+            clients.push(client);
+        }
+
+        let start = thread_cpu_ticks(&tid);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let spent = thread_cpu_ticks(&tid) - start;
+
+        // Clock ticks are usually 10ms, a spinning server would spend about 100 of them.
+        assert!(
+            spent < 10,
+            "{} idle clients used {} ticks",
+            IDLE_CLIENTS,
+            spent
+        );
+        drop(clients);
+    });
+}
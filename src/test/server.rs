@@ -1,22 +1,31 @@
 use std::collections::HashMap;
+use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
-
-use ipc_client::client::connector::Connector;
-use ipc_client::client::error::Error;
-use ipc_client::client::shared_object::{ObjectDispatcher, SharedObject};
-use ipc_client::client::wait_for_objects;
-use ipc_client::{ENV_LOGGER, ENV_SERVER_ADDRESS};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use futures_core::Stream;
 use json_elem::jsonelem::JsonElem;
-use tokio::runtime::Builder;
+use serde_derive::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::Mutex;
-use tokio::task::LocalSet;
+use tokio::task::JoinSet;
 
-use crate::manager::TaskManager;
-use crate::{setup_logger, Server};
+use crate::client::connector::{Connector, Subscription};
+use crate::client::error::{Error, ErrorKind};
+use crate::client::frame::FramedStream;
+use crate::client::message::{
+    CallObjectRequest, CallObjectResponse, Envelope, Event, IncomingMessage, ListObjects,
+    MethodDescription, OutgoingMessage, RegisterObject, RegisterPolicy, StaticReplies,
+    SubscribeToEvent, EVENT_OBJECT_REGISTERED, EVENT_OBJECT_UNREGISTERED, SERVER_OBJECT,
+};
+use crate::client::shared_object::{ObjectDispatcher, SharedObject};
+use crate::client::wait_for_objects::{self, WaitError};
+use crate::server::access::AccessRules;
+use crate::server::{ServerBuilder, ServerHandle};
+use crate::transport::{Address, ReadHalf, Transport};
+use crate::{ipc_object, ipc_proxy, CHUNK_SIZE};
 
 struct Mango;
 struct Apple;
@@ -51,126 +60,120 @@ impl SharedObject for Orange {
     }
 }
 
-fn find_available_port(start_port: u16) -> Option<u16> {
-    (start_port..=u16::MAX).find(|&port| std::net::TcpListener::bind(("127.0.0.1", port)).is_ok())
-}
-
-#[ctor::ctor]
-fn setup_server() {
-    std::env::set_var(ENV_LOGGER, "trace");
-    setup_logger();
-    let address = format!("127.0.0.1:{}", find_available_port(3000).unwrap());
-
-    std::env::set_var(ENV_SERVER_ADDRESS, address);
-    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
-
-    std::thread::spawn(move || {
-        let local = LocalSet::new();
-        local.spawn_local(async move {
-            let (tx, rx) = unbounded_channel();
-
-            // The server
-            let server = tokio::spawn(async move {
-                TaskManager::spawn(rx).await;
-                Server::spawn(tx).await;
-            });
-
-            let _ = server.await;
-        });
-        runtime.block_on(local);
-    });
+/// Starts a server of its own for a test, over the in-memory transport so no port is needed.
+async fn spawn_server(test: &str) -> (ServerHandle, Address) {
+    let server = ServerBuilder::new()
+        .bind(Address::Memory(test.to_string()))
+        .spawn()
+        .await
+        .unwrap();
+    let address = server.address().clone();
+    (server, address)
 }
 
 #[tokio::test]
 async fn test_server() {
+    let (_server, address) = spawn_server("test_server").await;
     // The process that shares objects
-    let _process1 = tokio::spawn(async move {
-        let mut shared = ObjectDispatcher::new().await.unwrap();
+    let _process1 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
 
-        shared
-            .register_object("mango", Box::new(Mango))
-            .await
-            .unwrap();
-        shared
-            .register_object("apple", Box::new(Apple))
-            .await
-            .unwrap();
-        shared
-            .register_object("orange", Box::new(Orange))
-            .await
-            .unwrap();
-        let _r = shared.spawn().await;
+            shared
+                .register_object("mango", Box::new(Mango))
+                .await
+                .unwrap();
+            shared
+                .register_object("apple", Box::new(Apple))
+                .await
+                .unwrap();
+            shared
+                .register_object("orange", Box::new(Orange))
+                .await
+                .unwrap();
+            let _r = shared.spawn().await;
+        }
     });
 
     let process2_result = Arc::new(Mutex::new(JsonElem::String(String::new())));
     let process2_result2 = process2_result.clone();
-    let process2 = tokio::spawn(async move {
-        // Wait for objects before connecting.
-        let list = vec![
-            "mango".to_string(),
-            "apple".to_string(),
-            "orange".to_string(),
-        ];
-        wait_for_objects::wait_for_objects(list).await;
-
-        let proxy = Connector::connect().await.unwrap();
-
-        let mut param = HashMap::new();
-        param.insert(
-            "provider".to_string(),
-            JsonElem::String("microsoft".to_string()),
-        );
+    let process2 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            // Wait for objects before connecting.
+            let list = vec![
+                "mango".to_string(),
+                "apple".to_string(),
+                "orange".to_string(),
+            ];
+            wait_for_objects::wait_for_objects_with(&address, list).await;
 
-        let result = proxy
-            .remote_call("mango", "login", Some(JsonElem::HashMap(param)))
-            .await
-            .unwrap();
-        log::trace!("[Process 2]: {}", result);
-        let mut actual = process2_result2.lock().await;
-        *actual = result;
+            let proxy = Connector::connect_with(&address).await.unwrap();
+
+            let mut param = HashMap::new();
+            param.insert(
+                "provider".to_string(),
+                JsonElem::String("microsoft".to_string()),
+            );
+
+            let result = proxy
+                .remote_call("mango", "login", Some(JsonElem::HashMap(param)))
+                .await
+                .unwrap();
+            log::trace!("[Process 2]: {}", result);
+            let mut actual = process2_result2.lock().await;
+            *actual = result;
+        }
     });
 
     let process3_result = Arc::new(Mutex::new(JsonElem::String(String::new())));
     let process3_result2 = process3_result.clone();
-    let process3 = tokio::spawn(async move {
-        // Wait for objects before connecting.
-        let list = vec![
-            "mango".to_string(),
-            "apple".to_string(),
-            "orange".to_string(),
-        ];
-        wait_for_objects::wait_for_objects(list).await;
-
-        let proxy = Connector::connect().await.unwrap();
-
-        let result = proxy.remote_call("apple", "login", None).await.unwrap();
-        log::trace!("[Process 3]: {}", result);
-
-        let mut actual = process3_result2.lock().await;
-        *actual = result;
+    let process3 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            // Wait for objects before connecting.
+            let list = vec![
+                "mango".to_string(),
+                "apple".to_string(),
+                "orange".to_string(),
+            ];
+            wait_for_objects::wait_for_objects_with(&address, list).await;
+
+            let proxy = Connector::connect_with(&address).await.unwrap();
+
+            let result = proxy.remote_call("apple", "login", None).await.unwrap();
+            log::trace!("[Process 3]: {}", result);
+
+            let mut actual = process3_result2.lock().await;
+            *actual = result;
+        }
     });
 
     let process4_result = Arc::new(Mutex::new(Error::new(JsonElem::String(String::new()))));
     let process4_result2 = process4_result.clone();
-    let process4 = tokio::spawn(async move {
-        // Wait for objects before connecting.
-        let list = vec![
-            "mango".to_string(),
-            "apple".to_string(),
-            "orange".to_string(),
-        ];
-        wait_for_objects::wait_for_objects(list).await;
-
-        let proxy = Connector::connect().await.unwrap();
-
-        let result = proxy
-            .remote_call("orange", "login", None)
-            .await
-            .unwrap_err();
-        log::trace!("[Process 4]: {}", result);
+    let process4 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            // Wait for objects before connecting.
+            let list = vec![
+                "mango".to_string(),
+                "apple".to_string(),
+                "orange".to_string(),
+            ];
+            wait_for_objects::wait_for_objects_with(&address, list).await;
+
+            let proxy = Connector::connect_with(&address).await.unwrap();
+
+            let result = proxy
+                .remote_call("orange", "login", None)
+                .await
+                .unwrap_err();
+            log::trace!("[Process 4]: {}", result);
 
-        let mut actual = process4_result2.lock().await;
-        *actual = result;
+            let mut actual = process4_result2.lock().await;
+            *actual = result;
+        }
     });
 
     let _ = tokio::join!(process2, process3, process4);
@@ -207,53 +210,329 @@ impl SharedObject for TestEvent {
 
 #[tokio::test]
 async fn test_event() {
+    let (_server, address) = spawn_server("test_event").await;
     // The process that shares objects
-    let process1 = tokio::spawn(async move {
-        let mut shared = ObjectDispatcher::new().await.unwrap();
+    let process1 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
 
-        shared
-            .register_object("event", Box::new(TestEvent))
-            .await
-            .unwrap();
-        let _r = shared.spawn().await;
+            shared
+                .register_object("event", Box::new(TestEvent))
+                .await
+                .unwrap();
+            let _r = shared.spawn().await;
+        }
     });
 
-    let process2 = tokio::spawn(async move {
-        // Wait for objects before connecting.
-        let list = vec!["event".to_string()];
-        wait_for_objects::wait_for_objects(list).await;
+    let process2 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            // Wait for objects before connecting.
+            let list = vec!["event".to_string()];
+            wait_for_objects::wait_for_objects_with(&address, list).await;
 
-        let proxy = Connector::connect().await.unwrap();
+            let proxy = Connector::connect_with(&address).await.unwrap();
 
-        for _n in 0..100 {
+            for _n in 0..100 {
+                proxy
+                    .send_event(
+                        "event",
+                        JsonElem::String("Sending you this event!!".to_string()),
+                    )
+                    .await
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+    });
+
+    let process3 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            // Wait for objects before connecting.
+            let list = vec!["event".to_string()];
+            wait_for_objects::wait_for_objects_with(&address, list).await;
+
+            let proxy = Connector::connect_with(&address).await.unwrap();
             proxy
-                .send_event(
-                    "event",
-                    JsonElem::String("Sending you this event!!".to_string()),
-                )
+                .listen_for_event("event", |param| async move {
+                    log::trace!("I HAVE RECEIVED: {param:?}");
+
+                    if param == JsonElem::String("Sending you this event!!".to_string()) {
+                        std::env::set_var("EVENT_TEST", true.to_string());
+                    }
+                    Ok::<(), Error>(())
+                })
                 .await
                 .unwrap();
-            tokio::time::sleep(Duration::from_millis(1)).await;
         }
     });
 
-    let process3 = tokio::spawn(async move {
-        // Wait for objects before connecting.
-        let list = vec!["event".to_string()];
-        wait_for_objects::wait_for_objects(list).await;
+    let (process1, process2, process3) = tokio::join!(process1, process2, process3);
 
-        let proxy = Connector::connect().await.unwrap();
-        proxy
-            .listen_for_event("event", |param| async move {
-                log::trace!("I HAVE RECEIVED: {param:?}");
+    process1.unwrap();
+    process2.unwrap();
+    process3.unwrap();
 
-                if param == JsonElem::String("Sending you this event!!".to_string()) {
-                    std::env::set_var("EVENT_TEST", true.to_string());
-                }
-                Ok::<(), Error>(())
-            })
+    assert_eq!(
+        std::env::var("EVENT_TEST").unwrap_or(false.to_string()),
+        true.to_string()
+    );
+}
+
+struct Echo;
+
+#[async_trait]
+impl SharedObject for Echo {
+    async fn remote_call(&self, method: &str, param: Option<JsonElem>) -> Result<JsonElem, Error> {
+        log::trace!("[Echo] Method: {}", method);
+
+        Ok(param.unwrap_or(JsonElem::HashMap(HashMap::new())))
+    }
+}
+
+#[tokio::test]
+async fn test_large_payload() {
+    let (_server, address) = spawn_server("test_large_payload").await;
+    let process1 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+
+            shared
+                .register_object("echo", Box::new(Echo))
+                .await
+                .unwrap();
+            let _r = shared.spawn().await;
+        }
+    });
+
+    let process2 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            wait_for_objects::wait_for_objects_with(&address, vec!["echo".to_string()]).await;
+
+            let proxy = Connector::connect_with(&address).await.unwrap();
+
+            // Payloads around the read chunk size and far beyond it must arrive intact.
+            for size in [CHUNK_SIZE - 2, CHUNK_SIZE, CHUNK_SIZE + 1, 100_000] {
+                let param = JsonElem::String("x".repeat(size));
+                let result = proxy
+                    .remote_call("echo", "echo", Some(param.clone()))
+                    .await
+                    .unwrap();
+                assert_eq!(result, param);
+            }
+        }
+    });
+
+    let (process1, process2) = tokio::join!(process1, process2);
+
+    process1.unwrap();
+    process2.unwrap();
+}
+
+#[tokio::test]
+async fn test_legacy_client() {
+    let (_server, address) = spawn_server("test_legacy_client").await;
+    let (mut reader, mut writer) = address.connect().await.unwrap();
+
+    // Clients without framing send bare JSON and get bare JSON back.
+    writer
+        .write_all(br#"{"reg_object":"legacy"}"#)
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; CHUNK_SIZE];
+    let n = reader.read(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], br#"{"success":"OK"}"#);
+
+    // A legacy process answers calls without an id.
+    let caller = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let proxy = Connector::connect_with(&address).await.unwrap();
+            proxy.remote_call("legacy", "login", None).await.unwrap()
+        }
+    });
+
+    let n = reader.read(&mut buf).await.unwrap();
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&buf[..n]).unwrap(),
+        serde_json::json!({"object": "legacy", "method": "login"})
+    );
+    writer
+        .write_all(br#"{"response":"This is my response from legacy"}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        caller.await.unwrap(),
+        JsonElem::String("This is my response from legacy".into())
+    );
+}
+
+#[tokio::test]
+async fn test_malformed_request() {
+    let (_server, address) = spawn_server("test_malformed_request").await;
+    let (reader, writer) = address.connect().await.unwrap();
+    let (mut reader, mut writer) = (FramedStream::new(reader), FramedStream::new(writer));
+
+    // A call without its object, and a message of an unknown type, are answered with an
+    // error carrying the id of the request.
+    let requests = [
+        serde_json::json!({"version": 1, "id": 5, "type": "call_request", "payload": {"method": "get"}}),
+        serde_json::json!({"version": 1, "id": 6, "type": "future_request", "payload": {}}),
+    ];
+    for request in requests {
+        writer
+            .write_frame(&serde_json::to_vec(&request).unwrap())
             .await
             .unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), reader.read_frame())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let reply: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(reply.id, request["id"].as_u64());
+        assert!(matches!(reply.message, IncomingMessage::Error(_)));
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_calls_on_one_connector() {
+    let (_server, address) = spawn_server("test_concurrent_calls_on_one_connector").await;
+    let process1 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+
+            shared
+                .register_object("pipeline", Box::new(Echo))
+                .await
+                .unwrap();
+            let _r = shared.spawn().await;
+        }
+    });
+
+    let process2 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            wait_for_objects::wait_for_objects_with(&address, vec!["pipeline".to_string()]).await;
+
+            let proxy = Connector::connect_with(&address).await.unwrap();
+
+            // A listening connector can still make calls.
+            proxy
+                .listen_for_event("pipeline event", |_| async move { Ok::<(), Error>(()) })
+                .await
+                .unwrap();
+
+            let mut calls = JoinSet::new();
+            for n in 0..20 {
+                let proxy = proxy.clone();
+                calls.spawn(async move {
+                    let param = JsonElem::Integer(n);
+                    let result = proxy
+                        .remote_call("pipeline", "echo", Some(param.clone()))
+                        .await
+                        .unwrap();
+                    // Every caller gets the response to its own request.
+                    assert_eq!(result, param);
+                });
+            }
+            while let Some(call) = calls.join_next().await {
+                call.unwrap();
+            }
+        }
+    });
+
+    let (process1, process2) = tokio::join!(process1, process2);
+
+    process1.unwrap();
+    process2.unwrap();
+}
+
+struct Slow;
+
+#[async_trait]
+impl SharedObject for Slow {
+    async fn remote_call(&self, method: &str, param: Option<JsonElem>) -> Result<JsonElem, Error> {
+        log::trace!("[Slow] Method: {} Param: {:?}", method, param);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        Ok(JsonElem::String("This is my response from slow".into()))
+    }
+}
+
+#[tokio::test]
+async fn test_slow_object_does_not_delay_other_calls() {
+    let (_server, address) = spawn_server("test_slow_object_does_not_delay_other_calls").await;
+    let process1 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+
+            shared
+                .register_object("slow", Box::new(Slow))
+                .await
+                .unwrap();
+            shared
+                .register_object("quick", Box::new(Echo))
+                .await
+                .unwrap();
+            let _r = shared.spawn().await;
+        }
+    });
+
+    let process2 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+
+            shared
+                .register_object("other", Box::new(Echo))
+                .await
+                .unwrap();
+            let _r = shared.spawn().await;
+        }
+    });
+
+    let process3 = tokio::spawn({
+        let address = address.clone();
+        async move {
+            let list = vec!["slow".to_string(), "quick".to_string(), "other".to_string()];
+            wait_for_objects::wait_for_objects_with(&address, list).await;
+
+            let proxy = Connector::connect_with(&address).await.unwrap();
+            let slow_proxy = proxy.clone();
+            let slow =
+                tokio::spawn(async move { slow_proxy.remote_call("slow", "wait", None).await });
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            // Calls to another process and to another object of the same process
+            // are answered while the slow call is still in progress.
+            let start = Instant::now();
+            let other = proxy
+                .remote_call("other", "echo", Some(JsonElem::Integer(1)))
+                .await
+                .unwrap();
+            let quick = proxy
+                .remote_call("quick", "echo", Some(JsonElem::Integer(2)))
+                .await
+                .unwrap();
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert_eq!(other, JsonElem::Integer(1));
+            assert_eq!(quick, JsonElem::Integer(2));
+            assert!(!slow.is_finished());
+
+            assert_eq!(
+                slow.await.unwrap().unwrap(),
+                JsonElem::String("This is my response from slow".into())
+            );
+        }
     });
 
     let (process1, process2, process3) = tokio::join!(process1, process2, process3);
@@ -261,9 +540,1446 @@ async fn test_event() {
     process1.unwrap();
     process2.unwrap();
     process3.unwrap();
+}
+
+#[tokio::test]
+async fn test_stalled_subscriber() {
+    let (_server, address) = spawn_server("test_stalled_subscriber").await;
+    // A subscriber that never reads its socket.
+    let (_reader, writer) = address.connect().await.unwrap();
+    let mut stalled = FramedStream::new(writer);
+    stalled
+        .write_frame(
+            &OutgoingMessage::SubscribeEvent(SubscribeToEvent::new("stalled event"))
+                .serialize()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let (received_tx, mut received_rx) = unbounded_channel();
+    let listener = Connector::connect_with(&address).await.unwrap();
+    listener
+        .listen_for_event("stalled event", move |param| {
+            let received_tx = received_tx.clone();
+            async move {
+                received_tx.send(param).unwrap();
+                Ok::<(), Error>(())
+            }
+        })
+        .await
+        .unwrap();
+    // Let the subscription of the stalled client reach the server before sending the events.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Far more data than the socket buffers of the stalled subscriber can hold.
+    let sender = Connector::connect_with(&address).await.unwrap();
+    let payload = JsonElem::String("x".repeat(64 * 1024));
+    for _n in 0..200 {
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            sender.send_event("stalled event", payload.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    }
+
+    for _n in 0..200 {
+        let received = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+            .await
+            .unwrap();
+        assert_eq!(received, Some(payload.clone()));
+    }
+}
 
+#[tokio::test]
+async fn test_shutdown() {
+    let server = ServerBuilder::new()
+        .bind(Address::Tcp("127.0.0.1:0".into()))
+        .spawn()
+        .await
+        .unwrap();
+    let address = server.address().clone();
+    assert_ne!(address, Address::Tcp("127.0.0.1:0".into()));
+
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    proxy
+        .send_event("shutdown event", JsonElem::Bool(true))
+        .await
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), server.shutdown())
+        .await
+        .unwrap();
+
+    // The connected clients are disconnected and no new client is accepted.
+    assert!(proxy
+        .send_event("shutdown event", JsonElem::Bool(true))
+        .await
+        .is_err());
+    assert!(Connector::connect_with(&address).await.is_err());
+}
+
+/// Starts a server with a provider of the slow object, and a call to it in flight.
+async fn spawn_slow_call(
+    test: &str,
+    shutdown_timeout: Duration,
+) -> (
+    ServerHandle,
+    Connector,
+    tokio::task::JoinHandle<Result<JsonElem, Error>>,
+) {
+    let server = ServerBuilder::new()
+        .bind(Address::Memory(test.to_string()))
+        .shutdown_timeout(shutdown_timeout)
+        .spawn()
+        .await
+        .unwrap();
+    let address = server.address().clone();
+
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("slow", Box::new(Slow))
+        .await
+        .unwrap();
+    let _provider = shared.spawn().await;
+
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let slow_proxy = proxy.clone();
+    let call = tokio::spawn(async move { slow_proxy.remote_call("slow", "wait", None).await });
+    // Let the call reach the provider.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (server, proxy, call)
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let (server, proxy, call) =
+        spawn_slow_call("test_graceful_shutdown", Duration::from_secs(5)).await;
+    let shutting_down = Error::new(JsonElem::String(
+        StaticReplies::ServerShuttingDown.to_string(),
+    ));
+
+    let start = Instant::now();
+    let shutdown = tokio::spawn(server.shutdown());
+
+    // The clients are notified, and new calls fail with a distinct error.
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while !proxy.is_shutting_down() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
     assert_eq!(
-        std::env::var("EVENT_TEST").unwrap_or(false.to_string()),
-        true.to_string()
+        proxy.remote_call("slow", "wait", None).await.unwrap_err(),
+        shutting_down
     );
+
+    // The call in flight is still answered, and the server stops right after it.
+    assert_eq!(
+        call.await.unwrap().unwrap(),
+        JsonElem::String("This is my response from slow".into())
+    );
+    shutdown.await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(4));
+}
+
+#[tokio::test]
+async fn test_shutdown_deadline() {
+    let (server, _proxy, call) =
+        spawn_slow_call("test_shutdown_deadline", Duration::from_millis(200)).await;
+
+    let start = Instant::now();
+    server.shutdown().await;
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // The call was still in flight when the deadline expired.
+    assert_eq!(
+        call.await.unwrap().unwrap_err(),
+        Error::new(JsonElem::String(
+            StaticReplies::ServerShuttingDown.to_string()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_dispatcher_shutdown() {
+    let (server, _proxy, call) =
+        spawn_slow_call("test_dispatcher_shutdown", Duration::from_secs(5)).await;
+    let address = server.address().clone();
+    let shutting_down = Error::new(JsonElem::String(
+        StaticReplies::ServerShuttingDown.to_string(),
+    ));
+
+    let mut spawned = ObjectDispatcher::with_transport(&address).await.unwrap();
+    spawned
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    let _spawned = spawned.spawn().await;
+    let mut waiting = ObjectDispatcher::with_transport(&address).await.unwrap();
+    waiting.hello(None).await.unwrap();
+    // A client that has not sent anything yet.
+    let fresh = Connector::connect_with(&address).await.unwrap();
+    // Let the server accept it.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let shutdown = tokio::spawn(server.shutdown());
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while !spawned.is_shutting_down() || !fresh.is_shutting_down() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        spawned
+            .register_object("apple", Box::new(Apple))
+            .await
+            .unwrap_err(),
+        shutting_down
+    );
+    assert_eq!(
+        fresh.remote_call("slow", "wait", None).await.unwrap_err(),
+        shutting_down
+    );
+
+    // The dispatcher that is not spawned yet reads the notice instead of a reply.
+    assert!(!waiting.is_shutting_down());
+    assert_eq!(
+        waiting
+            .register_object("apple", Box::new(Apple))
+            .await
+            .unwrap_err(),
+        shutting_down
+    );
+    assert!(waiting.is_shutting_down());
+
+    call.await.unwrap().unwrap();
+    shutdown.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_connection_limits() {
+    let server = ServerBuilder::new()
+        .bind(Address::Memory("test_connection_limits".into()))
+        .bind(Address::Tcp("127.0.0.1:0".into()))
+        .max_connections(1)
+        .max_frame_size(64)
+        .access(AccessRules {
+            allow: Vec::new(),
+            deny: vec!["127.0.0.0/8".parse().unwrap()],
+        })
+        .spawn()
+        .await
+        .unwrap();
+    let addresses = server.addresses().to_vec();
+    assert_eq!(addresses.len(), 2);
+
+    let connect = |address: Address| async move {
+        let (reader, writer) = address.connect().await.unwrap();
+        (FramedStream::new(reader), FramedStream::new(writer))
+    };
+    let closed = |mut reader: FramedStream<ReadHalf>| async move {
+        tokio::time::timeout(Duration::from_secs(1), reader.read_frame())
+            .await
+            .map(|frame| matches!(frame, Ok(None) | Err(_)))
+            .unwrap_or(false)
+    };
+
+    // The TCP clients are denied, while the in-memory ones have no IP address.
+    let (reader, _writer) = connect(addresses[1].clone()).await;
+    assert!(closed(reader).await);
+
+    // Only one client is served at once.
+    let (reader, writer) = connect(addresses[0].clone()).await;
+    let (second, _second_writer) = connect(addresses[0].clone()).await;
+    assert!(closed(second).await);
+    drop((reader, writer));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A message above the frame size closes the connection.
+    let (reader, mut writer) = connect(addresses[0].clone()).await;
+    writer.write_frame(&[b' '; 100]).await.unwrap();
+    assert!(closed(reader).await);
+}
+
+#[tokio::test]
+async fn test_call_timeout() {
+    let server = ServerBuilder::new()
+        .bind(Address::Memory("test_call_timeout".into()))
+        .call_timeout(Duration::from_millis(500))
+        .spawn()
+        .await
+        .unwrap();
+    let address = server.address().clone();
+    let timed_out = Error::new(JsonElem::String(StaticReplies::CallTimeout.to_string()));
+
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("slow", Box::new(Slow))
+        .await
+        .unwrap();
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    let _provider = shared.spawn().await;
+
+    // The default timeout of the server.
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let start = Instant::now();
+    assert_eq!(
+        proxy.remote_call("slow", "wait", None).await.unwrap_err(),
+        timed_out
+    );
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    assert!(start.elapsed() < Duration::from_millis(1500));
+
+    // The timeout set by the caller.
+    let (reader, writer) = address.connect().await.unwrap();
+    let (mut reader, mut writer) = (FramedStream::new(reader), FramedStream::new(writer));
+    let request =
+        CallObjectRequest::new("slow", "wait", None).with_timeout(Duration::from_millis(50));
+    let start = Instant::now();
+    writer
+        .write_frame(
+            &Envelope::new(OutgoingMessage::CallRequest(request))
+                .with_id(Some(7))
+                .serialize()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let frame = reader.read_frame().await.unwrap().unwrap();
+    let reply: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+    assert_eq!(reply.id, Some(7));
+    assert!(matches!(reply.message, IncomingMessage::Error(error) if error == timed_out));
+    assert!(start.elapsed() < Duration::from_millis(400));
+
+    // The late responses are discarded, and the provider keeps being served.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), reader.read_frame())
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        JsonElem::String("This is my response from mango".into())
+    );
+}
+
+#[tokio::test]
+async fn test_client_timeouts() {
+    let (_server, address) = spawn_server("test_client_timeouts").await;
+    let timed_out = Error::new(JsonElem::String(StaticReplies::CallTimeout.to_string()));
+
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("slow", Box::new(Slow))
+        .await
+        .unwrap();
+    let _provider = shared.spawn().await;
+
+    // Only some of the objects show up.
+    let list = vec!["slow".to_string(), "pear".to_string(), "kiwi".to_string()];
+    assert_eq!(
+        wait_for_objects::wait_for_objects_with_timeout(&address, list, Duration::from_millis(200))
+            .await,
+        Err(WaitError::Timeout(vec![
+            "pear".to_string(),
+            "kiwi".to_string()
+        ]))
+    );
+    wait_for_objects::wait_for_objects_with_timeout(
+        &address,
+        vec!["slow".to_string()],
+        Duration::from_secs(1),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        wait_for_objects::wait_for_objects_with_timeout(
+            &Address::Memory("test_client_timeouts_nowhere".into()),
+            vec!["slow".to_string()],
+            Duration::from_secs(1),
+        )
+        .await,
+        Err(WaitError::Connection(_))
+    ));
+
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let start = Instant::now();
+    assert_eq!(
+        proxy
+            .remote_call_with_timeout("slow", "wait", None, Duration::from_millis(200))
+            .await
+            .unwrap_err(),
+        timed_out
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let proxy = proxy.with_timeout(Duration::from_millis(200));
+    let start = Instant::now();
+    assert_eq!(
+        proxy.remote_call("slow", "wait", None).await.unwrap_err(),
+        timed_out
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_wait_for_objects() {
+    let (_server, address) = spawn_server("test_wait_for_objects").await;
+    let list = vec!["mango".to_string(), "apple".to_string()];
+    let wait = tokio::spawn({
+        let address = address.clone();
+        let list = list.clone();
+        async move {
+            wait_for_objects::wait_for_objects_with_timeout(
+                &address,
+                list,
+                Duration::from_millis(300),
+            )
+            .await
+        }
+    });
+
+    // An object that went away before the last one showed up is still waited for.
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    shared.unregister_object("mango").await.unwrap();
+    shared
+        .register_object("apple", Box::new(Apple))
+        .await
+        .unwrap();
+    assert_eq!(
+        wait.await.unwrap(),
+        Err(WaitError::Timeout(vec!["mango".to_string()]))
+    );
+
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        wait_for_objects::wait_for_objects_with(&address, list),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_wait_for_objects_on_legacy_server() {
+    let address = Address::Memory("test_wait_for_objects_on_legacy_server".into());
+    let mut listener = address.bind().await.unwrap();
+    // A server that answers without an id, with an empty list until the second request.
+    let server = tokio::spawn(async move {
+        let (reader, writer, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = (FramedStream::new(reader), FramedStream::new(writer));
+        for available in [false, true] {
+            let frame = reader.read_frame().await.unwrap().unwrap();
+            let request: Envelope<OutgoingMessage> = serde_json::from_slice(&frame).unwrap();
+            let OutgoingMessage::WaitForObjects(request) = request.message else {
+                panic!("Unexpected request {:?}", request);
+            };
+            let list = if available { request.list } else { Vec::new() };
+            writer
+                .write_frame(
+                    &Envelope::new(IncomingMessage::WaitForObjects(ListObjects::new(list)))
+                        .serialize()
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+    });
+
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        wait_for_objects::wait_for_objects_with(&address, vec!["mango".to_string()]),
+    )
+    .await
+    .unwrap();
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_wait_for_objects_is_answered_on_register() {
+    let (_server, address) = spawn_server("test_wait_for_objects_is_answered_on_register").await;
+
+    let (reader, writer) = address.connect().await.unwrap();
+    let (mut reader, mut writer) = (FramedStream::new(reader), FramedStream::new(writer));
+    let list = vec!["late".to_string(), "later".to_string()];
+    writer
+        .write_frame(
+            &Envelope::new(OutgoingMessage::WaitForObjects(ListObjects::new(
+                list.clone(),
+            )))
+            .with_id(Some(3))
+            .serialize()
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // The request is held by the server instead of being answered with an empty list.
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    for object in &list {
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), reader.read_frame())
+                .await
+                .is_err()
+        );
+        shared
+            .register_object(object, Box::new(Mango))
+            .await
+            .unwrap();
+    }
+
+    let frame = tokio::time::timeout(Duration::from_secs(1), reader.read_frame())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let reply: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+    assert_eq!(reply.id, Some(3));
+    assert!(matches!(reply.message, IncomingMessage::WaitForObjects(v) if v.list == list));
+}
+
+#[tokio::test]
+async fn test_registry_events() {
+    let (_server, address) = spawn_server("test_registry_events").await;
+
+    let (events_tx, mut events_rx) = unbounded_channel();
+    let supervisor = Connector::connect_with(&address).await.unwrap();
+    for event in [EVENT_OBJECT_REGISTERED, EVENT_OBJECT_UNREGISTERED] {
+        let events_tx = events_tx.clone();
+        supervisor
+            .listen_for_event(event, move |param| {
+                let events_tx = events_tx.clone();
+                async move {
+                    events_tx.send((event, param)).unwrap();
+                    Ok::<(), Error>(())
+                }
+            })
+            .await
+            .unwrap();
+    }
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    let welcome = shared.hello(Some("fruit seller")).await.unwrap();
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    let (event, param) = events_rx.recv().await.unwrap();
+    assert_eq!(event, EVENT_OBJECT_REGISTERED);
+    let owner = JsonElem::Integer(welcome.client_id as i32);
+    match &param {
+        JsonElem::HashMap(result) => {
+            assert_eq!(result["object"], JsonElem::String("mango".into()));
+            assert_eq!(result["owner"], owner);
+        }
+        param => panic!("Unexpected result {:?}", param),
+    };
+
+    // The provider crashes.
+    drop(shared);
+    let (event, param) = tokio::time::timeout(Duration::from_secs(1), events_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, EVENT_OBJECT_UNREGISTERED);
+    match param {
+        JsonElem::HashMap(result) => {
+            assert_eq!(result["object"], JsonElem::String("mango".into()));
+            assert_eq!(result["owner"], owner);
+        }
+        param => panic!("Unexpected result {:?}", param),
+    }
+
+    // Only the server sends the built-in events.
+    assert_eq!(
+        supervisor
+            .send_event(EVENT_OBJECT_UNREGISTERED, JsonElem::Bool(true))
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String(StaticReplies::ReservedEvent.to_string()))
+    );
+    // Even when they are sent as they are.
+    let (reader, writer) = address.connect().await.unwrap();
+    let (mut reader, mut writer) = (FramedStream::new(reader), FramedStream::new(writer));
+    writer
+        .write_frame(
+            &Envelope::new(OutgoingMessage::SendEvent(Event::new(
+                EVENT_OBJECT_UNREGISTERED,
+                JsonElem::Bool(true),
+            )))
+            .with_id(Some(4))
+            .serialize()
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    let frame = reader.read_frame().await.unwrap().unwrap();
+    let reply: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+    assert_eq!(reply.id, Some(4));
+    assert!(matches!(
+        reply.message,
+        IncomingMessage::Error(error) if error == Error::new(JsonElem::String(StaticReplies::ReservedEvent.to_string()))
+    ));
+}
+
+#[tokio::test]
+async fn test_unregister_object() {
+    let (_server, address) = spawn_server("test_unregister_object").await;
+    let error = |reply: StaticReplies| Error::new(JsonElem::String(reply.to_string()));
+
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    shared
+        .register_object("apple", Box::new(Apple))
+        .await
+        .unwrap();
+    let _provider = shared.spawn().await;
+
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    proxy.remote_call("mango", "get", None).await.unwrap();
+
+    // The plugin goes away while the process and its other objects stay.
+    shared.unregister_object("mango").await.unwrap();
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap_err(),
+        error(StaticReplies::ObjectNotFound)
+    );
+    assert_eq!(
+        proxy.remote_call("apple", "get", None).await.unwrap(),
+        JsonElem::String("This is my response from apple".into())
+    );
+    assert_eq!(
+        shared.unregister_object("mango").await.unwrap_err(),
+        error(StaticReplies::ObjectNotFound)
+    );
+
+    // Only the owner can unregister an object.
+    let mut other = ObjectDispatcher::with_transport(&address).await.unwrap();
+    assert_eq!(
+        other.unregister_object("apple").await.unwrap_err(),
+        error(StaticReplies::NotOwner)
+    );
+
+    // And the plugin comes back.
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        JsonElem::String("This is my response from mango".into())
+    );
+}
+
+#[tokio::test]
+async fn test_register_policy() {
+    let (_server, address) = spawn_server("test_register_policy").await;
+    let response = |fruit: &str| JsonElem::String(format!("This is my response from {}", fruit));
+
+    let mut owner = ObjectDispatcher::with_transport(&address).await.unwrap();
+    owner
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    let _owner = owner.spawn().await;
+    let proxy = Connector::connect_with(&address).await.unwrap();
+
+    // A second process cannot take the name over by default.
+    let mut rival = ObjectDispatcher::with_transport(&address).await.unwrap();
+    assert_eq!(
+        rival
+            .register_object("mango", Box::new(Apple))
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String(
+            StaticReplies::AlreadyRegistered.to_string()
+        ))
+    );
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        response("mango")
+    );
+
+    // Processes on standby wait for their turn, in order.
+    let mut standby = ObjectDispatcher::with_transport(&address).await.unwrap();
+    standby
+        .register_object_with_policy("mango", Box::new(Orange), RegisterPolicy::Standby)
+        .await
+        .unwrap();
+    let standby_task = standby.spawn().await;
+    let mut second = ObjectDispatcher::with_transport(&address).await.unwrap();
+    second
+        .register_object_with_policy("mango", Box::new(Mango), RegisterPolicy::Standby)
+        .await
+        .unwrap();
+    let _second = second.spawn().await;
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        response("mango")
+    );
+
+    // A new version of the process takes the name over.
+    rival
+        .register_object_with_policy("mango", Box::new(Apple), RegisterPolicy::Replace)
+        .await
+        .unwrap();
+    let _rival = rival.spawn().await;
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        response("apple")
+    );
+
+    // The first process on standby takes over once the object is unregistered...
+    rival.unregister_object("mango").await.unwrap();
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap_err(),
+        Error::new(JsonElem::String("exception happend".into()))
+    );
+
+    // ...and the next one once that process goes away.
+    standby_task.abort();
+    drop(standby);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        response("mango")
+    );
+}
+
+#[tokio::test]
+async fn test_replaced_owner() {
+    let (_server, address) = spawn_server("test_replaced_owner").await;
+
+    let (reader, writer) = address.connect().await.unwrap();
+    let (mut reader, mut writer) = (FramedStream::new(reader), FramedStream::new(writer));
+    writer
+        .write_frame(
+            &Envelope::new(OutgoingMessage::Register(RegisterObject::new("mango")))
+                .with_id(Some(1))
+                .serialize()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let frame = reader.read_frame().await.unwrap().unwrap();
+    let reply: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+    assert!(matches!(reply.message, IncomingMessage::Register(_)));
+
+    // A call the owner is not done with yet.
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let call = tokio::spawn({
+        let proxy = proxy.clone();
+        async move { proxy.remote_call("mango", "get", None).await }
+    });
+    let frame = reader.read_frame().await.unwrap().unwrap();
+    let request: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+    assert!(matches!(request.message, IncomingMessage::CallRequest(_)));
+
+    // The client on standby takes the name over, and leaves the queue.
+    let mut rival = ObjectDispatcher::with_transport(&address).await.unwrap();
+    rival
+        .register_object_with_policy("mango", Box::new(Orange), RegisterPolicy::Standby)
+        .await
+        .unwrap();
+    rival
+        .register_object_with_policy("mango", Box::new(Apple), RegisterPolicy::Replace)
+        .await
+        .unwrap();
+    let _rival = rival.spawn().await;
+    let description = proxy
+        .remote_call(
+            SERVER_OBJECT,
+            "describe_object",
+            Some(JsonElem::HashMap(HashMap::from([(
+                "object".to_string(),
+                JsonElem::String("mango".into()),
+            )]))),
+        )
+        .await
+        .unwrap();
+    let JsonElem::HashMap(description) = description else {
+        panic!("Unexpected description {:?}", description);
+    };
+    assert_eq!(description["standby"], JsonElem::Vec(Vec::new()));
+
+    // The call forwarded to the previous owner fails, and the previous owner is told.
+    assert_eq!(
+        call.await.unwrap().unwrap_err(),
+        Error::new(JsonElem::String(StaticReplies::ObjectReplaced.to_string()))
+    );
+    let frame = reader.read_frame().await.unwrap().unwrap();
+    let event: Envelope<IncomingMessage> = serde_json::from_slice(&frame).unwrap();
+    assert!(matches!(
+        event.message,
+        IncomingMessage::Event(event) if event.event == EVENT_OBJECT_UNREGISTERED
+    ));
+
+    // Its late response is discarded.
+    writer
+        .write_frame(
+            &Envelope::new(OutgoingMessage::CallResponse(CallObjectResponse::new(
+                JsonElem::String("late".into()),
+            )))
+            .with_id(request.id)
+            .serialize()
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        proxy.remote_call("mango", "get", None).await.unwrap(),
+        JsonElem::String("This is my response from apple".into())
+    );
+}
+
+#[tokio::test]
+async fn test_client_ids() {
+    let (_server, address) = spawn_server("test_client_ids").await;
+
+    // Every connection gets an id of its own, whatever its transport tells about its peer.
+    let first = Connector::connect_with(&address).await.unwrap();
+    let second = Connector::connect_with(&address).await.unwrap();
+    let first = first.hello(Some("first")).await.unwrap();
+    let second = second.hello(None).await.unwrap();
+    assert_ne!(first.client_id, second.client_id);
+    assert_eq!(first.name, Some("first".to_string()));
+    assert_eq!(second.name, None);
+}
+
+#[tokio::test]
+async fn test_introspection() {
+    let (_server, address) = spawn_server("test_introspection").await;
+    let list = |items: Vec<JsonElem>| JsonElem::Vec(items);
+    let map = |entries: Vec<(&str, JsonElem)>| {
+        JsonElem::HashMap(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    };
+    let string = |value: &str| JsonElem::String(value.to_string());
+
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    let owner = shared.hello(Some("fruit seller")).await.unwrap().client_id as i32;
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    let _shared = shared.spawn().await;
+
+    let mut standby = ObjectDispatcher::with_transport(&address).await.unwrap();
+    let standby_id = standby.hello(None).await.unwrap().client_id as i32;
+    standby
+        .register_object_with_policy("mango", Box::new(Apple), RegisterPolicy::Standby)
+        .await
+        .unwrap();
+
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let proxy_id = proxy.hello(Some("operator")).await.unwrap().client_id as i32;
+    proxy
+        .listen_for_event("ripe", |_| async { Ok::<(), Error>(()) })
+        .await
+        .unwrap();
+    assert_eq!(
+        proxy
+            .remote_call(SERVER_OBJECT, "list_objects", None)
+            .await
+            .unwrap(),
+        list(vec![map(vec![
+            ("object", string("mango")),
+            ("owner", JsonElem::Integer(owner)),
+        ])])
+    );
+    assert_eq!(
+        proxy
+            .remote_call(SERVER_OBJECT, "list_subscriptions", None)
+            .await
+            .unwrap(),
+        map(vec![("ripe", list(vec![JsonElem::Integer(proxy_id)]))])
+    );
+
+    let clients = proxy
+        .remote_call(SERVER_OBJECT, "list_clients", None)
+        .await
+        .unwrap();
+    let clients = match clients {
+        JsonElem::Vec(clients) => clients,
+        clients => panic!("Unexpected result {:?}", clients),
+    };
+    assert_eq!(clients.len(), 3);
+    match &clients[0] {
+        JsonElem::HashMap(client) => {
+            assert_eq!(client["id"], JsonElem::Integer(owner));
+            assert_eq!(client["name"], string("fruit seller"));
+            assert_eq!(client["objects"], list(vec![string("mango")]));
+            assert_eq!(client["subscriptions"], list(vec![]));
+        }
+        client => panic!("Unexpected client {:?}", client),
+    }
+
+    let description = proxy
+        .remote_call(
+            SERVER_OBJECT,
+            "describe_object",
+            Some(map(vec![("object", string("mango"))])),
+        )
+        .await
+        .unwrap();
+    match description {
+        JsonElem::HashMap(description) => {
+            assert_eq!(description["object"], string("mango"));
+            match (&description["owner"], &description["standby"]) {
+                (JsonElem::HashMap(owner_info), JsonElem::Vec(standby)) => {
+                    assert_eq!(owner_info["id"], JsonElem::Integer(owner));
+                    assert_eq!(standby.len(), 1);
+                    match &standby[0] {
+                        JsonElem::HashMap(info) => {
+                            assert_eq!(info["id"], JsonElem::Integer(standby_id))
+                        }
+                        info => panic!("Unexpected client {:?}", info),
+                    }
+                }
+                description => panic!("Unexpected description {:?}", description),
+            }
+        }
+        description => panic!("Unexpected description {:?}", description),
+    }
+
+    let error = |reply: StaticReplies| Error::new(JsonElem::String(reply.to_string()));
+    assert_eq!(
+        proxy
+            .remote_call(
+                SERVER_OBJECT,
+                "describe_object",
+                Some(map(vec![("object", string("apple"))])),
+            )
+            .await
+            .unwrap_err(),
+        error(StaticReplies::ObjectNotFound)
+    );
+    assert_eq!(
+        proxy
+            .remote_call(SERVER_OBJECT, "describe_object", None)
+            .await
+            .unwrap_err(),
+        error(StaticReplies::InvalidParameter)
+    );
+    assert_eq!(
+        proxy
+            .remote_call(SERVER_OBJECT, "reboot", None)
+            .await
+            .unwrap_err(),
+        error(StaticReplies::MethodNotFound)
+    );
+
+    // Nobody else can share the object of the server.
+    assert_eq!(
+        standby
+            .register_object(SERVER_OBJECT, Box::new(Apple))
+            .await
+            .unwrap_err(),
+        error(StaticReplies::ReservedObject)
+    );
+}
+
+struct Basket;
+
+#[async_trait]
+impl SharedObject for Basket {
+    async fn remote_call(&self, method: &str, param: Option<JsonElem>) -> Result<JsonElem, Error> {
+        log::trace!("[Basket] Method: {} Param: {:?}", method, param);
+
+        Ok(JsonElem::String(format!("{} done", method)))
+    }
+
+    fn methods(&self) -> Option<Vec<MethodDescription>> {
+        let schema: JsonElem = serde_json::from_str(
+            r#"{"type": "object", "properties": {"fruit": {"type": "string"}}, "required": ["fruit"]}"#,
+        )
+        .unwrap();
+        Some(vec![
+            MethodDescription::new("add")
+                .with_description("Puts a fruit in the basket.")
+                .with_params(schema),
+            MethodDescription::new("count"),
+        ])
+    }
+}
+
+#[tokio::test]
+async fn test_method_schema() {
+    let server = ServerBuilder::new()
+        .bind(Address::Memory("test_method_schema".to_string()))
+        .validate_params(true)
+        .spawn()
+        .await
+        .unwrap();
+    let address = server.address().clone();
+
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("basket", Box::new(Basket))
+        .await
+        .unwrap();
+    shared
+        .register_object("mango", Box::new(Mango))
+        .await
+        .unwrap();
+    let _shared = shared.spawn().await;
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let param = |value: &str| serde_json::from_str::<JsonElem>(value).unwrap();
+
+    // The methods are told to the clients.
+    let description = proxy
+        .remote_call(
+            SERVER_OBJECT,
+            "describe_object",
+            Some(param(r#"{"object": "basket"}"#)),
+        )
+        .await
+        .unwrap();
+    let methods: Vec<MethodDescription> = match description {
+        JsonElem::HashMap(description) => description["methods"].convert_to().unwrap(),
+        description => panic!("Unexpected description {:?}", description),
+    };
+    assert_eq!(methods, Basket.methods().unwrap());
+
+    // The calls are checked before they reach the object.
+    assert_eq!(
+        proxy
+            .remote_call("basket", "add", Some(param(r#"{"fruit": "mango"}"#)))
+            .await
+            .unwrap(),
+        JsonElem::String("add done".into())
+    );
+    assert_eq!(
+        proxy
+            .remote_call("basket", "add", Some(param(r#"{"fruit": 3}"#)))
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String(
+            "invalid parameter: param.fruit must be of type string".into()
+        ))
+    );
+    assert_eq!(
+        proxy.remote_call("basket", "eat", None).await.unwrap_err(),
+        Error::new(JsonElem::String(StaticReplies::MethodNotFound.to_string()))
+    );
+    assert_eq!(
+        proxy.remote_call("basket", "count", None).await.unwrap(),
+        JsonElem::String("count done".into())
+    );
+
+    // The objects that describe nothing get every call.
+    assert_eq!(
+        proxy.remote_call("mango", "eat", None).await.unwrap(),
+        JsonElem::String("This is my response from mango".into())
+    );
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Fruit {
+    name: String,
+    ripe: bool,
+}
+
+struct Stall {
+    fruits: Mutex<Vec<Fruit>>,
+}
+
+#[ipc_object]
+impl Stall {
+    /// Puts the fruit on the stall.
+    async fn add(&self, fruit: Fruit, count: Option<u32>) -> Result<usize, String> {
+        if !fruit.ripe {
+            return Err(format!("{} is not ripe", fruit.name));
+        }
+        let mut fruits = self.fruits.lock().await;
+        for _ in 0..count.unwrap_or(1) {
+            fruits.push(fruit.clone());
+        }
+        Ok(fruits.len())
+    }
+
+    /// Lists the fruits
+    /// on the stall.
+    async fn list(&self) -> Vec<Fruit> {
+        self.fruits.lock().await.clone()
+    }
+
+    fn clear(&self) {}
+
+    /// Returns the kind of fruit it is given.
+    fn r#type(&self, kind: Option<String>) -> Option<String> {
+        kind
+    }
+
+    #[ipc(skip)]
+    fn restock(&mut self) {}
+}
+
+#[tokio::test]
+async fn test_ipc_object() {
+    let (_server, address) = spawn_server("test_ipc_object").await;
+    let mut stall = Stall {
+        fruits: Mutex::new(Vec::new()),
+    };
+    // The skipped methods stay as they are.
+    stall.restock();
+    let methods = stall.methods().unwrap();
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object("stall", Box::new(stall))
+        .await
+        .unwrap();
+    let _shared = shared.spawn().await;
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let param = |value: &str| serde_json::from_str::<JsonElem>(value).unwrap();
+
+    assert_eq!(
+        proxy
+            .remote_call(
+                "stall",
+                "add",
+                Some(param(
+                    r#"{"fruit": {"name": "mango", "ripe": true}, "count": 2}"#
+                ))
+            )
+            .await
+            .unwrap(),
+        JsonElem::Integer(2)
+    );
+    assert_eq!(
+        proxy
+            .remote_call(
+                "stall",
+                "add",
+                Some(param(r#"{"fruit": {"name": "apple", "ripe": false}}"#))
+            )
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String("apple is not ripe".into()))
+    );
+    let fruits: Vec<Fruit> = proxy
+        .remote_call("stall", "list", None)
+        .await
+        .unwrap()
+        .convert_to()
+        .unwrap();
+    assert_eq!(
+        fruits,
+        vec![
+            Fruit {
+                name: "mango".into(),
+                ripe: true
+            };
+            2
+        ]
+    );
+    assert_eq!(
+        proxy.remote_call("stall", "clear", None).await.unwrap(),
+        JsonElem::String(StaticReplies::Ok.to_string())
+    );
+    assert_eq!(
+        proxy
+            .remote_call("stall", "add", Some(param(r#"{"count": 1}"#)))
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String(
+            "invalid parameter: fruit: it is required".into()
+        ))
+    );
+    // A method that returned None answers "OK" to the callers of JsonElem, which has no null.
+    assert_eq!(
+        proxy
+            .remote_call("stall", "type", Some(param(r#"{"kind": "OK"}"#)))
+            .await
+            .unwrap(),
+        JsonElem::String("OK".into())
+    );
+    assert_eq!(
+        proxy
+            .remote_call_value("stall", "type", None)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        proxy
+            .remote_call("stall", "restock", None)
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String(StaticReplies::MethodNotFound.to_string()))
+    );
+
+    // The methods are described from their signature and their doc comments.
+    let names: Vec<&str> = methods.iter().map(|method| method.name.as_str()).collect();
+    assert_eq!(names, vec!["add", "list", "clear", "type"]);
+    assert_eq!(
+        methods[0].description.as_deref(),
+        Some("Puts the fruit on the stall.")
+    );
+    assert_eq!(
+        methods[0].params,
+        Some(param(
+            r#"{"type": "object", "properties": {"fruit": {}, "count": {"anyOf": [{"type": "integer"}, {"type": "null"}]}}, "required": ["fruit"]}"#
+        ))
+    );
+    assert_eq!(methods[0].result, Some(param(r#"{"type": "integer"}"#)));
+    assert_eq!(
+        methods[1].description.as_deref(),
+        Some("Lists the fruits\non the stall.")
+    );
+    assert_eq!(
+        methods[1].result,
+        Some(param(r#"{"type": "array", "items": {}}"#))
+    );
+    assert_eq!(methods[2].params, None);
+    assert_eq!(methods[2].result, None);
+}
+
+/// The methods of Stall, as the clients see them.
+#[ipc_proxy]
+// Only its proxy is used here.
+#[allow(dead_code)]
+trait FruitStall {
+    /// Puts the fruit on the stall.
+    async fn add(&self, fruit: &Fruit, count: Option<u32>) -> Result<usize, String>;
+    async fn list(&self) -> Vec<Fruit>;
+    fn clear(&self);
+    fn r#type(&self, kind: Option<&str>) -> Option<String>;
+}
+
+#[tokio::test]
+async fn test_ipc_proxy() {
+    let (_server, address) = spawn_server("test_ipc_proxy").await;
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object(
+            "stall",
+            Box::new(Stall {
+                fruits: Mutex::new(Vec::new()),
+            }),
+        )
+        .await
+        .unwrap();
+    let _shared = shared.spawn().await;
+    let stall = FruitStallProxy::new(Connector::connect_with(&address).await.unwrap(), "stall");
+    let mango = Fruit {
+        name: "mango".into(),
+        ripe: true,
+    };
+
+    assert_eq!(stall.add(&mango, Some(2)).await.unwrap(), 2);
+    assert_eq!(stall.add(&mango, None).await.unwrap(), 3);
+    assert_eq!(
+        stall
+            .add(
+                &Fruit {
+                    name: "apple".into(),
+                    ripe: false
+                },
+                None
+            )
+            .await
+            .unwrap_err(),
+        Error::new(JsonElem::String("apple is not ripe".into()))
+    );
+    assert_eq!(stall.list().await.unwrap(), vec![mango; 3]);
+    stall.clear().await.unwrap();
+    // "OK" is a value like any other, and None is told apart from it.
+    assert_eq!(
+        stall.r#type(Some("OK")).await.unwrap(),
+        Some("OK".to_string())
+    );
+    assert_eq!(stall.r#type(None).await.unwrap(), None);
+
+    let missing = FruitStallProxy::new(stall.connector().clone(), "barrow");
+    assert_eq!(
+        missing.list().await.unwrap_err(),
+        Error::new(JsonElem::String(StaticReplies::ObjectNotFound.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_typed_calls_and_events() {
+    let (_server, address) = spawn_server("test_typed_calls_and_events").await;
+    let mut shared = ObjectDispatcher::with_transport(&address).await.unwrap();
+    shared
+        .register_object(
+            "stall",
+            Box::new(Stall {
+                fruits: Mutex::new(Vec::new()),
+            }),
+        )
+        .await
+        .unwrap();
+    let _shared = shared.spawn().await;
+    let proxy = Connector::connect_with(&address).await.unwrap();
+    let mango = Fruit {
+        name: "mango".into(),
+        ripe: true,
+    };
+
+    let count: usize = proxy
+        .call(
+            "stall",
+            "add",
+            &serde_json::json!({"fruit": mango, "count": 2}),
+        )
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+    let fruits: Vec<Fruit> = proxy.call("stall", "list", &()).await.unwrap();
+    assert_eq!(fruits, vec![mango.clone(); 2]);
+    proxy.call::<_, ()>("stall", "clear", &()).await.unwrap();
+    let kind: Option<String> = proxy
+        .call("stall", "type", &serde_json::json!({"kind": "OK"}))
+        .await
+        .unwrap();
+    assert_eq!(kind, Some("OK".to_string()));
+    let kind: Option<String> = proxy.call("stall", "type", &()).await.unwrap();
+    assert_eq!(kind, None);
+    let kind: serde_json::Value = proxy
+        .call("stall", "type", &serde_json::json!({"kind": "OK"}))
+        .await
+        .unwrap();
+    assert_eq!(kind, serde_json::json!("OK"));
+    let error = proxy
+        .call::<_, String>("stall", "list", &())
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Deserialize);
+    let error = proxy
+        .call::<_, usize>("stall", "add", &serde_json::json!({"count": 1}))
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Other);
+
+    let (fruits_tx, mut fruits_rx) = unbounded_channel();
+    let listener = Connector::connect_with(&address).await.unwrap();
+    listener
+        .listen_for_event_typed("fruit", move |fruit: Fruit| {
+            let fruits_tx = fruits_tx.clone();
+            async move {
+                fruits_tx.send(fruit).unwrap();
+                Ok::<(), Error>(())
+            }
+        })
+        .await
+        .unwrap();
+    // The events that are not fruits are skipped.
+    proxy
+        .send_event("fruit", JsonElem::Bool(true))
+        .await
+        .unwrap();
+    proxy.send_event_typed("fruit", &mango).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(1), fruits_rx.recv())
+        .await
+        .unwrap();
+    assert_eq!(received, Some(mango));
+    assert_eq!(
+        proxy
+            .send_event_typed("fruit", &())
+            .await
+            .unwrap_err()
+            .kind(),
+        ErrorKind::Serialize
+    );
+}
+
+/// Polls the subscription as a Stream for its next event.
+async fn next_event(subscription: &mut Subscription) -> Option<Event> {
+    let mut subscription = Pin::new(subscription);
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        std::future::poll_fn(|cx| subscription.as_mut().poll_next(cx)),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let (server, address) = spawn_server("test_subscribe").await;
+    let subscriber = Connector::connect_with(&address).await.unwrap();
+    let subscriber_id = subscriber.hello(None).await.unwrap().client_id as i32;
+    let mut ripe = subscriber.subscribe("ripe").await.unwrap();
+    let mut also_ripe = subscriber.subscribe("ripe").await.unwrap();
+    let mut rotten = subscriber.subscribe("rotten").await.unwrap();
+    assert_eq!(ripe.event(), "ripe");
+    let subscriptions = || async {
+        subscriber
+            .remote_call(SERVER_OBJECT, "list_subscriptions", None)
+            .await
+            .unwrap()
+    };
+    // The subscriber still makes calls.
+    let JsonElem::HashMap(events) = subscriptions().await else {
+        panic!("Unexpected subscriptions");
+    };
+    assert_eq!(
+        events["ripe"],
+        JsonElem::Vec(vec![JsonElem::Integer(subscriber_id)])
+    );
+    assert!(events.contains_key("rotten"));
+
+    let sender = Connector::connect_with(&address).await.unwrap();
+    sender
+        .send_event("ripe", JsonElem::String("mango".into()))
+        .await
+        .unwrap();
+    sender
+        .send_event("rotten", JsonElem::String("apple".into()))
+        .await
+        .unwrap();
+    for subscription in [&mut ripe, &mut also_ripe] {
+        let event = next_event(subscription).await.unwrap();
+        assert_eq!(event.event, "ripe");
+        assert_eq!(event.result, JsonElem::String("mango".into()));
+    }
+    let event = rotten.recv().await.unwrap();
+    assert_eq!(event.result, JsonElem::String("apple".into()));
+
+    // The server is told once the last subscription of the event is dropped.
+    drop(ripe);
+    sender
+        .send_event("ripe", JsonElem::String("banana".into()))
+        .await
+        .unwrap();
+    let event = next_event(&mut also_ripe).await.unwrap();
+    assert_eq!(event.result, JsonElem::String("banana".into()));
+    assert!(
+        matches!(subscriptions().await, JsonElem::HashMap(events) if events.contains_key("ripe"))
+    );
+    drop(also_ripe);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let JsonElem::HashMap(events) = subscriptions().await else {
+        panic!("Unexpected subscriptions");
+    };
+    assert!(!events.contains_key("ripe"));
+    assert!(events.contains_key("rotten"));
+
+    // The subscriptions end with the connection.
+    server.shutdown().await;
+    assert!(next_event(&mut rotten).await.is_none());
 }
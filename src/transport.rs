@@ -0,0 +1,248 @@
+pub mod memory;
+pub mod tcp;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix;
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use self::tls::{TlsOptions, TlsTransport};
+use crate::{ENV_SERVER_ADDRESS, SERVER_ADDRESS};
+
+/// The scheme that selects a Unix domain socket, as in `unix:/run/ipc.sock`.
+pub const UNIX_SCHEME: &str = "unix:";
+/// The optional scheme of a TCP address, as in `tcp:127.0.0.1:1986`.
+pub const TCP_SCHEME: &str = "tcp:";
+/// The scheme that selects TCP encrypted with TLS, as in `tls:ipc.example.com:1986`.
+pub const TLS_SCHEME: &str = "tls:";
+/// The scheme that selects an in-memory transport within the process, as in `memory:test`.
+pub const MEMORY_SCHEME: &str = "memory:";
+
+/// A way of reaching the IPC server. The server and all the clients only deal with
+/// the halves of a connection, so any transport can carry the protocol.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Connects to the IPC server and returns both halves of the connection.
+    async fn connect(&self) -> std::io::Result<(ReadHalf, WriteHalf)>;
+
+    /// Binds a listener that accepts the connections of the IPC clients.
+    async fn bind(&self) -> std::io::Result<Box<dyn Listener>>;
+}
+
+/// The server side of a transport.
+#[async_trait]
+pub trait Listener: Send + fmt::Debug {
+    /// Accepts a new connection. Returns both halves of the connection and a name
+    /// that identifies the peer.
+    async fn accept(&mut self) -> std::io::Result<(ReadHalf, WriteHalf, String)>;
+
+    /// Returns the address the listener is bound to.
+    fn local_addr(&self) -> std::io::Result<Address>;
+}
+
+/// The address of the IPC server. The transport is selected by the scheme of the address,
+/// an address without a scheme is a TCP address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+    Tls(String),
+    Memory(String),
+}
+
+impl Address {
+    /// Returns the address from ENV_SERVER_ADDRESS, or SERVER_ADDRESS if it is not set.
+    pub fn from_env() -> std::io::Result<Self> {
+        std::env::var(ENV_SERVER_ADDRESS)
+            .unwrap_or(SERVER_ADDRESS.to_owned())
+            .parse()
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix(UNIX_SCHEME) {
+            if path.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("missing socket path in {}", s),
+                ));
+            }
+            Ok(Address::Unix(PathBuf::from(path)))
+        } else if let Some(address) = s.strip_prefix(TLS_SCHEME) {
+            Ok(Address::Tls(address.to_string()))
+        } else if let Some(name) = s.strip_prefix(MEMORY_SCHEME) {
+            Ok(Address::Memory(name.to_string()))
+        } else {
+            Ok(Address::Tcp(
+                s.strip_prefix(TCP_SCHEME).unwrap_or(s).to_string(),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}", address),
+            Address::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+            Address::Tls(address) => write!(f, "{}{}", TLS_SCHEME, address),
+            Address::Memory(name) => write!(f, "{}{}", MEMORY_SCHEME, name),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for Address {
+    async fn connect(&self) -> std::io::Result<(ReadHalf, WriteHalf)> {
+        match self {
+            Address::Tcp(address) => tcp::connect(address).await,
+            #[cfg(unix)]
+            Address::Unix(path) => unix::connect(path).await,
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(unsupported()),
+            Address::Tls(address) => {
+                TlsTransport::new(address, TlsOptions::from_env())
+                    .connect()
+                    .await
+            }
+            Address::Memory(name) => memory::connect(name),
+        }
+    }
+
+    async fn bind(&self) -> std::io::Result<Box<dyn Listener>> {
+        match self {
+            Address::Tcp(address) => Ok(Box::new(tcp::bind(address).await?)),
+            #[cfg(unix)]
+            Address::Unix(path) => Ok(Box::new(unix::UnixSocket::bind(path).await?)),
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(unsupported()),
+            Address::Tls(address) => {
+                TlsTransport::new(address, TlsOptions::from_env())
+                    .bind()
+                    .await
+            }
+            Address::Memory(name) => Ok(Box::new(memory::MemoryListener::bind(name)?)),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "Unix domain sockets are not supported on this platform",
+    )
+}
+
+/// The read half of a connection, whatever its transport.
+pub struct ReadHalf(Pin<Box<dyn AsyncRead + Send>>);
+
+/// The write half of a connection, whatever its transport.
+pub struct WriteHalf(Pin<Box<dyn AsyncWrite + Send>>);
+
+impl ReadHalf {
+    pub fn new(reader: impl AsyncRead + Send + 'static) -> Self {
+        Self(Box::pin(reader))
+    }
+}
+
+impl WriteHalf {
+    pub fn new(writer: impl AsyncWrite + Send + 'static) -> Self {
+        Self(Box::pin(writer))
+    }
+}
+
+impl fmt::Debug for ReadHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReadHalf")
+    }
+}
+
+impl fmt::Debug for WriteHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WriteHalf")
+    }
+}
+
+impl AsyncRead for ReadHalf {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.0.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for WriteHalf {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.0.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.0.as_mut().poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::client::frame::FramedStream;
+    use crate::transport::{Address, Transport};
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            "127.0.0.1:1986".parse::<Address>().unwrap(),
+            Address::Tcp("127.0.0.1:1986".into())
+        );
+        assert_eq!(
+            "tcp:127.0.0.1:1986".parse::<Address>().unwrap(),
+            Address::Tcp("127.0.0.1:1986".into())
+        );
+        let address = "unix:/run/ipc.sock".parse::<Address>().unwrap();
+        assert_eq!(address, Address::Unix(PathBuf::from("/run/ipc.sock")));
+        assert_eq!(address.to_string(), "unix:/run/ipc.sock");
+        assert!("unix:".parse::<Address>().is_err());
+        let address = "memory:test".parse::<Address>().unwrap();
+        assert_eq!(address, Address::Memory("test".into()));
+        assert_eq!(address.to_string(), "memory:test");
+        let address = "tls:localhost:1986".parse::<Address>().unwrap();
+        assert_eq!(address, Address::Tls("localhost:1986".into()));
+        assert_eq!(address.to_string(), "tls:localhost:1986");
+    }
+
+    #[tokio::test]
+    async fn test_tcp_transport() {
+        let mut listener = Address::Tcp("127.0.0.1:0".into()).bind().await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let (_, writer) = address.connect().await.unwrap();
+        let (reader, _, _) = listener.accept().await.unwrap();
+
+        let mut writer = FramedStream::new(writer);
+        let mut reader = FramedStream::new(reader);
+        writer.write_frame(b"hello").await.unwrap();
+        assert_eq!(reader.read_frame().await.unwrap(), Some(b"hello".to_vec()));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use super::{Address, Listener, ReadHalf, WriteHalf, MEMORY_SCHEME};
+
+/// The bytes an in-memory connection buffers in each direction before the writer waits,
+/// like the buffers of a socket.
+pub const MEMORY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The in-memory listeners of this process, by name.
+static LISTENERS: Mutex<BTreeMap<String, UnboundedSender<DuplexStream>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Connects to the in-memory listener with this name.
+pub fn connect(name: &str) -> std::io::Result<(ReadHalf, WriteHalf)> {
+    let (client, server) = tokio::io::duplex(MEMORY_BUFFER_SIZE);
+    let listeners = LISTENERS.lock().unwrap_or_else(|e| e.into_inner());
+    match listeners.get(name) {
+        Some(listener) if listener.send(server).is_ok() => {
+            let (reader, writer) = tokio::io::split(client);
+            Ok((ReadHalf::new(reader), WriteHalf::new(writer)))
+        }
+        _ => Err(Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("nothing is listening on {}{}", MEMORY_SCHEME, name),
+        )),
+    }
+}
+
+/// A listener for connections within this process, so the server and its clients
+/// can talk without binding any port. It stops listening when it is dropped.
+#[derive(Debug)]
+pub struct MemoryListener {
+    name: String,
+    rx: UnboundedReceiver<DuplexStream>,
+    accepted: u64,
+}
+
+impl MemoryListener {
+    /// Listens on the name, unless another listener already uses it.
+    pub fn bind(name: &str) -> std::io::Result<Self> {
+        let mut listeners = LISTENERS.lock().unwrap_or_else(|e| e.into_inner());
+        if listeners.contains_key(name) {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("another server is listening on {}{}", MEMORY_SCHEME, name),
+            ));
+        }
+        let (tx, rx) = unbounded_channel();
+        listeners.insert(name.to_string(), tx);
+        Ok(Self {
+            name: name.to_string(),
+            rx,
+            accepted: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl Listener for MemoryListener {
+    /// The peers are numbered in the order they connect.
+    async fn accept(&mut self) -> std::io::Result<(ReadHalf, WriteHalf, String)> {
+        let stream = self
+            .rx
+            .recv()
+            .await
+            .ok_or_else(|| Error::new(ErrorKind::BrokenPipe, "in-memory listener is closed"))?;
+        self.accepted += 1;
+        let name = format!("{}{}#{}", MEMORY_SCHEME, self.name, self.accepted);
+        let (reader, writer) = tokio::io::split(stream);
+        Ok((ReadHalf::new(reader), WriteHalf::new(writer), name))
+    }
+
+    fn local_addr(&self) -> std::io::Result<Address> {
+        Ok(Address::Memory(self.name.clone()))
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        LISTENERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::frame::FramedStream;
+    use crate::transport::{Address, Transport};
+
+    #[tokio::test]
+    async fn test_memory_transport() {
+        let address = Address::Memory("test_memory_transport".into());
+        assert!(address.connect().await.is_err());
+
+        let mut listener = address.bind().await.unwrap();
+        assert!(address.bind().await.is_err());
+
+        let (reader, writer) = address.connect().await.unwrap();
+        let (server_reader, server_writer, name) = listener.accept().await.unwrap();
+        assert_eq!(name, "memory:test_memory_transport#1");
+
+        let mut client = (FramedStream::new(reader), FramedStream::new(writer));
+        let mut server = (
+            FramedStream::new(server_reader),
+            FramedStream::new(server_writer),
+        );
+        client.1.write_frame(b"ping").await.unwrap();
+        assert_eq!(server.0.read_frame().await.unwrap(), Some(b"ping".to_vec()));
+        server.1.write_frame(b"pong").await.unwrap();
+        assert_eq!(client.0.read_frame().await.unwrap(), Some(b"pong".to_vec()));
+
+        drop(listener);
+        assert!(address.connect().await.is_err());
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+use tokio::net::{TcpListener, TcpStream};
+
+use super::{Address, Listener, ReadHalf, WriteHalf};
+
+/// Connects to the IPC server over TCP.
+pub async fn connect(address: &str) -> std::io::Result<(ReadHalf, WriteHalf)> {
+    let (reader, writer) = TcpStream::connect(address).await?.into_split();
+    Ok((ReadHalf::new(reader), WriteHalf::new(writer)))
+}
+
+/// Binds a TCP listener.
+pub async fn bind(address: &str) -> std::io::Result<TcpListener> {
+    TcpListener::bind(address).await
+}
+
+#[async_trait]
+impl Listener for TcpListener {
+    /// The peer is named after its IP address.
+    async fn accept(&mut self) -> std::io::Result<(ReadHalf, WriteHalf, String)> {
+        let (stream, peer) = TcpListener::accept(self).await?;
+        let (reader, writer) = stream.into_split();
+        Ok((
+            ReadHalf::new(reader),
+            WriteHalf::new(writer),
+            peer.to_string(),
+        ))
+    }
+
+    fn local_addr(&self) -> std::io::Result<Address> {
+        Ok(Address::Tcp(TcpListener::local_addr(self)?.to_string()))
+    }
+}
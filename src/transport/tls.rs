@@ -0,0 +1,351 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use super::{Address, Listener, ReadHalf, Transport, WriteHalf};
+use crate::{ENV_TLS_CA, ENV_TLS_CERT, ENV_TLS_KEY, ENV_TLS_SERVER_NAME};
+
+/// The time a client has to complete the TLS handshake once connected.
+pub const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The certificates and keys of a TLS connection, as paths to PEM files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// The certificate chain of this side. It is required by the server, and makes a client
+    /// authenticate itself when the server verifies client certificates.
+    pub cert: Option<PathBuf>,
+    /// The private key of the certificate.
+    pub key: Option<PathBuf>,
+    /// The certificate authorities trusted to sign the certificate of the other side.
+    /// It is required by clients, and makes the server require client certificates.
+    pub ca: Option<PathBuf>,
+    /// The name the certificate of the server is verified against.
+    /// Clients use the host of the address if it is not set.
+    pub server_name: Option<String>,
+}
+
+impl TlsOptions {
+    /// Reads the options from ENV_TLS_CERT, ENV_TLS_KEY, ENV_TLS_CA and ENV_TLS_SERVER_NAME.
+    pub fn from_env() -> Self {
+        Self {
+            cert: std::env::var_os(ENV_TLS_CERT).map(PathBuf::from),
+            key: std::env::var_os(ENV_TLS_KEY).map(PathBuf::from),
+            ca: std::env::var_os(ENV_TLS_CA).map(PathBuf::from),
+            server_name: std::env::var(ENV_TLS_SERVER_NAME).ok(),
+        }
+    }
+}
+
+/// TCP encrypted with TLS.
+#[derive(Debug, Clone)]
+pub struct TlsTransport {
+    address: String,
+    options: TlsOptions,
+}
+
+impl TlsTransport {
+    pub fn new(address: &str, options: TlsOptions) -> Self {
+        Self {
+            address: address.to_string(),
+            options,
+        }
+    }
+
+    /// Loads the certificates and the key the server is bound with, so a missing or invalid
+    /// file shows up before the server starts.
+    pub fn check_server(&self) -> std::io::Result<()> {
+        self.server_config().map(|_| ())
+    }
+
+    fn client_config(&self) -> std::io::Result<ClientConfig> {
+        let ca = self.options.ca.as_deref().ok_or_else(|| {
+            invalid(format!(
+                "{} is required to verify the server certificate",
+                ENV_TLS_CA
+            ))
+        })?;
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid)?
+            .with_root_certificates(load_roots(ca)?);
+
+        match (&self.options.cert, &self.options.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .map_err(invalid),
+            _ => Ok(builder.with_no_client_auth()),
+        }
+    }
+
+    fn server_config(&self) -> std::io::Result<ServerConfig> {
+        let (cert, key) = match (&self.options.cert, &self.options.key) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => {
+                return Err(invalid(format!(
+                    "{} and {} are required by the server",
+                    ENV_TLS_CERT, ENV_TLS_KEY
+                )))
+            }
+        };
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid)?;
+
+        let builder = match &self.options.ca {
+            Some(ca) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(load_roots(ca)?),
+                    provider(),
+                )
+                .build()
+                .map_err(invalid)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        builder
+            .with_single_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(invalid)
+    }
+
+    fn server_name(&self) -> std::io::Result<ServerName<'static>> {
+        let name = match &self.options.server_name {
+            Some(name) => name.as_str(),
+            None => self
+                .address
+                .rsplit_once(':')
+                .map_or(self.address.as_str(), |(host, _)| host)
+                .trim_start_matches('[')
+                .trim_end_matches(']'),
+        };
+        ServerName::try_from(name.to_string()).map_err(invalid)
+    }
+}
+
+#[async_trait]
+impl Transport for TlsTransport {
+    async fn connect(&self) -> std::io::Result<(ReadHalf, WriteHalf)> {
+        let connector = TlsConnector::from(Arc::new(self.client_config()?));
+        let stream = TcpStream::connect(&self.address).await?;
+        let stream = connector.connect(self.server_name()?, stream).await?;
+        let (reader, writer) = tokio::io::split(stream);
+        Ok((ReadHalf::new(reader), WriteHalf::new(writer)))
+    }
+
+    async fn bind(&self) -> std::io::Result<Box<dyn Listener>> {
+        let acceptor = TlsAcceptor::from(Arc::new(self.server_config()?));
+        let listener = TcpListener::bind(&self.address).await?;
+        let address = listener.local_addr()?.to_string();
+        let (tx, rx) = unbounded_channel();
+
+        // The handshakes run in their own tasks, so a client that never completes
+        // its handshake does not hold back the others.
+        let task = tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        if tx.send(Err(e)).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                    {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send(Ok((stream, peer)));
+                        }
+                        Ok(Err(e)) => log::error!("[{}]: TLS handshake failed: {}", peer, e),
+                        Err(_) => log::error!("[{}]: TLS handshake timed out", peer),
+                    }
+                });
+            }
+        });
+
+        Ok(Box::new(TlsListener { address, rx, task }))
+    }
+}
+
+/// A TCP listener that only hands over the clients that completed the TLS handshake.
+#[derive(Debug)]
+pub struct TlsListener {
+    address: String,
+    rx: UnboundedReceiver<std::io::Result<(TlsStream<TcpStream>, SocketAddr)>>,
+    task: JoinHandle<()>,
+}
+
+#[async_trait]
+impl Listener for TlsListener {
+    /// The peer is named after its IP address.
+    async fn accept(&mut self) -> std::io::Result<(ReadHalf, WriteHalf, String)> {
+        let (stream, peer) = self
+            .rx
+            .recv()
+            .await
+            .ok_or_else(|| Error::new(ErrorKind::BrokenPipe, "TLS listener is closed"))??;
+        let (reader, writer) = tokio::io::split(stream);
+        Ok((
+            ReadHalf::new(reader),
+            WriteHalf::new(writer),
+            peer.to_string(),
+        ))
+    }
+
+    fn local_addr(&self) -> std::io::Result<Address> {
+        Ok(Address::Tls(self.address.clone()))
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn invalid(e: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidInput, e.to_string())
+}
+
+fn load_certs(path: &Path) -> std::io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+}
+
+fn load_key(path: &Path) -> std::io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+}
+
+fn load_roots(path: &Path) -> std::io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+
+    use crate::client::frame::FramedStream;
+    use crate::transport::tls::{TlsOptions, TlsTransport};
+    use crate::transport::{Listener, Transport};
+
+    /// Writes a certificate authority, and a certificate and key signed by it for each name.
+    fn generate_certificates(dir: &Path, names: &[&str]) {
+        std::fs::create_dir_all(dir).unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        for name in names {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca)
+                .unwrap();
+            std::fs::write(dir.join(format!("{}.pem", name)), cert.pem()).unwrap();
+            std::fs::write(dir.join(format!("{}.key", name)), key.serialize_pem()).unwrap();
+        }
+    }
+
+    fn options(dir: &Path, name: Option<&str>, ca: bool) -> TlsOptions {
+        TlsOptions {
+            cert: name.map(|name| dir.join(format!("{}.pem", name))),
+            key: name.map(|name| dir.join(format!("{}.key", name))),
+            ca: ca.then(|| dir.join("ca.pem")),
+            server_name: None,
+        }
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ipc-{}-{}", test, std::process::id()))
+    }
+
+    async fn ping(transport: &TlsTransport, listener: &mut Box<dyn Listener>) {
+        let (_, writer) = transport.connect().await.unwrap();
+        let (reader, _, _) = listener.accept().await.unwrap();
+        let mut writer = FramedStream::new(writer);
+        let mut reader = FramedStream::new(reader);
+        writer.write_frame(b"hello").await.unwrap();
+        assert_eq!(reader.read_frame().await.unwrap(), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_tls_transport() {
+        let dir = temp_dir("test_tls_transport");
+        generate_certificates(&dir, &["localhost"]);
+        let other = dir.join("other");
+        generate_certificates(&other, &[]);
+
+        let server = TlsTransport::new("localhost:0", options(&dir, Some("localhost"), false));
+        let mut listener = server.bind().await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let port = address.rsplit_once(':').unwrap().1;
+        let address = format!("localhost:{}", port);
+
+        let client = TlsTransport::new(&address, options(&dir, None, true));
+        ping(&client, &mut listener).await;
+
+        // A server signed by an authority the client does not trust.
+        let client = TlsTransport::new(&address, options(&other, None, true));
+        assert!(client.connect().await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let dir = temp_dir("test_mutual_tls");
+        generate_certificates(&dir, &["localhost", "client"]);
+
+        let server = TlsTransport::new("localhost:0", options(&dir, Some("localhost"), true));
+        let mut listener = server.bind().await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let address = format!("localhost:{}", address.rsplit_once(':').unwrap().1);
+
+        // Without a client certificate the server drops the connection.
+        let client = TlsTransport::new(&address, options(&dir, None, true));
+        let rejected = tokio::time::timeout(Duration::from_secs(5), async {
+            let (reader, _writer) = client.connect().await?;
+            FramedStream::new(reader).read_frame().await
+        })
+        .await
+        .unwrap();
+        assert!(!matches!(rejected, Ok(Some(_))));
+
+        let client = TlsTransport::new(&address, options(&dir, Some("client"), true));
+        ping(&client, &mut listener).await;
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
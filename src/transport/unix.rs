@@ -0,0 +1,167 @@
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use tokio::net::{UnixListener, UnixStream};
+
+use super::{Address, Listener, ReadHalf, Transport, WriteHalf, UNIX_SCHEME};
+use crate::{ENV_SOCKET_MODE, SOCKET_MODE};
+
+/// Connects to the IPC server over a Unix domain socket.
+pub async fn connect(path: &Path) -> std::io::Result<(ReadHalf, WriteHalf)> {
+    let (reader, writer) = UnixStream::connect(path).await?.into_split();
+    Ok((ReadHalf::new(reader), WriteHalf::new(writer)))
+}
+
+/// A Unix domain socket whose file gets the given permissions, instead of the ones
+/// from ENV_SOCKET_MODE.
+#[derive(Debug, Clone)]
+pub struct UnixTransport {
+    path: PathBuf,
+    mode: u32,
+}
+
+impl UnixTransport {
+    pub fn new(path: &Path, mode: u32) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            mode,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for UnixTransport {
+    async fn connect(&self) -> std::io::Result<(ReadHalf, WriteHalf)> {
+        connect(&self.path).await
+    }
+
+    async fn bind(&self) -> std::io::Result<Box<dyn Listener>> {
+        Ok(Box::new(
+            UnixSocket::bind_with_mode(&self.path, self.mode).await?,
+        ))
+    }
+}
+
+/// A bound Unix domain socket. The socket file is removed when it is dropped.
+#[derive(Debug)]
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    accepted: u64,
+}
+
+impl UnixSocket {
+    /// Binds the socket to the path.
+    ///
+    /// A socket left behind by a server that is no longer running is removed first,
+    /// and the socket file gets the permissions from ENV_SOCKET_MODE, or SOCKET_MODE
+    /// if it is not set.
+    pub async fn bind(path: &Path) -> std::io::Result<Self> {
+        Self::bind_with_mode(path, socket_mode()?).await
+    }
+
+    /// Binds the socket to the path, and gives the socket file these permissions.
+    pub async fn bind_with_mode(path: &Path, mode: u32) -> std::io::Result<Self> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(Error::new(
+                    ErrorKind::AddrInUse,
+                    format!("another server is listening on {}", path.display()),
+                ));
+            }
+            log::info!("Removing stale socket {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            accepted: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl Listener for UnixSocket {
+    /// Unix domain socket peers have no address, they are numbered in the order they connect.
+    async fn accept(&mut self) -> std::io::Result<(ReadHalf, WriteHalf, String)> {
+        let (stream, _) = self.listener.accept().await?;
+        self.accepted += 1;
+        let name = format!("{}{}#{}", UNIX_SCHEME, self.path.display(), self.accepted);
+        let (reader, writer) = stream.into_split();
+        Ok((ReadHalf::new(reader), WriteHalf::new(writer), name))
+    }
+
+    fn local_addr(&self) -> std::io::Result<Address> {
+        Ok(Address::Unix(self.path.clone()))
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).unwrap_or_else(|e| {
+            log::error!("{}: {:?}", self.path.display(), e);
+        });
+    }
+}
+
+/// Returns the permissions of the socket file.
+fn socket_mode() -> std::io::Result<u32> {
+    match std::env::var(ENV_SOCKET_MODE) {
+        Ok(mode) => u32::from_str_radix(mode.trim_start_matches("0o"), 8).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid {} {}: {}", ENV_SOCKET_MODE, mode, e),
+            )
+        }),
+        Err(_) => Ok(SOCKET_MODE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::client::frame::FramedStream;
+    use crate::transport::{Address, Transport};
+
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("ipc-test-{}.sock", std::process::id()));
+        let address = Address::Unix(path.clone());
+
+        // A socket file left behind by a server that is gone.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let mut listener = address.bind().await.unwrap();
+        assert_eq!(listener.local_addr().unwrap(), address);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, crate::SOCKET_MODE);
+
+        let (_, writer) = address.connect().await.unwrap();
+        let (reader, _, name) = listener.accept().await.unwrap();
+        assert!(name.starts_with("unix:"));
+
+        let mut writer = FramedStream::new(writer);
+        let mut reader = FramedStream::new(reader);
+        writer.write_frame(b"hello").await.unwrap();
+        assert_eq!(reader.read_frame().await.unwrap(), Some(b"hello".to_vec()));
+
+        // The socket is in use, so it must not be taken over.
+        assert!(address.bind().await.is_err());
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}